@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::opcodes::{self, OpCode};
+
+/// Sums a page of memory forever: loads, stores, ALU ops, a read-modify-write,
+/// taken and untaken branches and a jump.
+const PROGRAM: [u8; 18] = [
+    0xa2, 0x00, //       LDX #$00
+    0xbd, 0x00, 0x02, // loop: LDA $0200,X
+    0x69, 0x01, //       ADC #$01
+    0x9d, 0x00, 0x02, // STA $0200,X
+    0xe8, //             INX
+    0xd0, 0xf5, //       BNE loop
+    0xe6, 0x10, //       INC $10
+    0x4c, 0x00, 0x80, // JMP $8000
+];
+
+const INSTRUCTIONS: u64 = 10_000;
+
+fn cpu(variant: CpuVariant) -> CPU {
+    let mut cpu = CPU::with_variant(variant);
+    cpu.load(PROGRAM.to_vec());
+    cpu.reset();
+    cpu
+}
+
+fn step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    for (name, variant) in [("2a03", CpuVariant::Ricoh2A03), ("65c02", CpuVariant::Cmos65C02)] {
+        let mut cpu = cpu(variant);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    black_box(cpu.step().unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    let mut cpu = cpu(CpuVariant::Ricoh2A03);
+    group.bench_function("2a03", |b| {
+        b.iter(|| {
+            let mut instructions = 0;
+            while instructions < INSTRUCTIONS {
+                if cpu.tick().unwrap().is_some() {
+                    instructions += 1;
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Decoding alone: the static table `step` indexes against the
+/// `HashMap<u8, &OpCode>` lookup it replaced, over the opcodes `PROGRAM` runs.
+fn dispatch(c: &mut Criterion) {
+    let codes = [0xbd, 0x69, 0x9d, 0xe8, 0xd0, 0xe6, 0x4c, 0xa2];
+    let table = opcodes::opcode_table(CpuVariant::Ricoh2A03);
+    let map: HashMap<u8, &OpCode> = table.iter().map(|opcode| (opcode.code, opcode)).collect();
+
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("table", |b| {
+        b.iter(|| {
+            for i in 0..INSTRUCTIONS as usize {
+                let code = black_box(codes[i % codes.len()]);
+                black_box(&table[code as usize]);
+            }
+        })
+    });
+    group.bench_function("hashmap", |b| {
+        b.iter(|| {
+            for i in 0..INSTRUCTIONS as usize {
+                let code = black_box(codes[i % codes.len()]);
+                black_box(map.get(&code).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, step, tick, dispatch);
+criterion_main!(benches);
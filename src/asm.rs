@@ -0,0 +1,745 @@
+//! A small two-pass 6502 assembler, built on the same opcode tables as the CPU.
+//!
+//! ```text
+//!         .org $8000
+//! count = 3
+//! start:  LDX #count
+//! loop:   DEX
+//!         BNE loop
+//!         STA $0200,X
+//!         JMP (vector)
+//! vector: .word start
+//! text:   .byte "hi", 0
+//! ```
+//!
+//! Operands use the usual syntax for every addressing mode. Expressions take
+//! `$hex`, `%binary`, decimal and `'c'` numbers, labels, `*` for the current
+//! address, `<` and `>` for the low and high byte, and `+ - * / & | ^ << >>`
+//! with parentheses. An operand that starts with `(` is always indirect.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::cpu::{AddressingMode, CpuVariant};
+use crate::disasm::Symbols;
+use crate::opcodes::{self, Instruction, OpCode};
+
+/// Where code goes when the source has no `.org`: the address `CPU::load` uses.
+pub const DEFAULT_ORIGIN: u16 = 0x8000;
+
+/// An assembly error at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// The assembled program: one block of memory from the lowest address written
+/// to the highest, with any gaps between `.org` sections zero-filled.
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    /// Labels and constants, by name.
+    pub labels: HashMap<String, u16>,
+}
+
+impl Assembly {
+    /// The labels as a table for `disasm`, keyed by address.
+    pub fn symbols(&self) -> Symbols {
+        self.labels.iter().map(|(name, &address)| (address, name.clone())).collect()
+    }
+}
+
+/// Assembles `source` for `variant`.
+pub fn assemble(variant: CpuVariant, source: &str) -> Result<Assembly, AsmError> {
+    let mut assembler = Assembler {
+        table: opcodes::opcode_table(variant),
+        symbols: HashMap::new(),
+        wide: Vec::new(),
+        output: Vec::new(),
+        pc: DEFAULT_ORIGIN as u32,
+        final_pass: false,
+        operands: 0,
+    };
+    assembler.pass(source)?;
+    assembler.final_pass = true;
+    assembler.pass(source)?;
+
+    let (origin, bytes) = match (
+        assembler.output.iter().map(|&(address, _)| address).min(),
+        assembler.output.iter().map(|&(address, _)| address).max(),
+    ) {
+        (Some(low), Some(high)) => {
+            let mut bytes = vec![0; (high - low) as usize + 1];
+            for &(address, byte) in &assembler.output {
+                bytes[(address - low) as usize] = byte;
+            }
+            (low, bytes)
+        }
+        _ => (DEFAULT_ORIGIN, Vec::new()),
+    };
+    let labels = assembler
+        .symbols
+        .into_iter()
+        .map(|(name, value)| (name, value as u16))
+        .collect();
+    Ok(Assembly { origin, bytes, labels })
+}
+
+/// Assembles NES code for a test and returns the bytes `CPU::load` expects.
+/// Panics, pointing at the caller, if the source is invalid or does not start
+/// at `DEFAULT_ORIGIN`, where `CPU::load` puts it.
+#[track_caller]
+pub fn program(source: &str) -> Vec<u8> {
+    match assemble(CpuVariant::Ricoh2A03, source) {
+        Ok(assembly) if assembly.origin != DEFAULT_ORIGIN => panic!(
+            "program is assembled at ${:04X}, but CPU::load puts it at ${:04X}",
+            assembly.origin, DEFAULT_ORIGIN
+        ),
+        Ok(assembly) => assembly.bytes,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Str(Vec<u8>),
+    Punct(&'static str),
+}
+
+#[derive(Debug, Clone)]
+struct Spanned {
+    token: Token,
+    column: usize,
+}
+
+const PUNCTUATION: [&str; 17] = [
+    "<<", ">>", "#", "(", ")", ",", "+", "-", "*", "/", "&", "|", "^", "<", ">", ":", "=",
+];
+
+fn lex(text: &str, line: usize) -> Result<Vec<Spanned>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let error = |column: usize, message: String| AsmError { line, column, message };
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == ';' {
+            break;
+        }
+
+        let token = if c == '$' || c == '%' || c.is_ascii_digit() {
+            let (radix, start) = match c {
+                '$' => (16, i + 1),
+                '%' => (2, i + 1),
+                _ => (10, i),
+            };
+            let mut end = start;
+            while end < chars.len() && chars[end].is_ascii_alphanumeric() {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().collect();
+            let text: String = chars[i..end].iter().collect();
+            i = end;
+            match i64::from_str_radix(&digits, radix) {
+                Ok(value) if value <= 0xffff_ffff => Token::Number(value),
+                _ => return Err(error(column, format!("invalid number `{}`", text))),
+            }
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let mut end = i + 1;
+            while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_' || chars[end] == '.') {
+                end += 1;
+            }
+            let name: String = chars[i..end].iter().collect();
+            i = end;
+            Token::Ident(name)
+        } else if c == '\'' {
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some(&value), Some('\'')) if value.is_ascii() => {
+                    i += 3;
+                    Token::Number(value as i64)
+                }
+                _ => return Err(error(column, "invalid character literal".to_string())),
+            }
+        } else if c == '"' {
+            let mut bytes = Vec::new();
+            i += 1;
+            loop {
+                let c = match chars.get(i) {
+                    Some('"') => break,
+                    Some('\\') => {
+                        i += 1;
+                        match chars.get(i) {
+                            Some('n') => '\n',
+                            Some('r') => '\r',
+                            Some('0') => '\0',
+                            Some(&c @ ('"' | '\\')) => c,
+                            _ => return Err(error(i + 1, "unknown escape in string".to_string())),
+                        }
+                    }
+                    Some(&c) if c.is_ascii() => c,
+                    Some(_) => return Err(error(i + 1, "strings must be ASCII".to_string())),
+                    None => return Err(error(column, "unterminated string".to_string())),
+                };
+                bytes.push(c as u8);
+                i += 1;
+            }
+            i += 1;
+            Token::Str(bytes)
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match PUNCTUATION.iter().find(|punct| rest.starts_with(*punct)) {
+                Some(punct) => {
+                    i += punct.len();
+                    Token::Punct(punct)
+                }
+                None => return Err(error(column, format!("unexpected character `{}`", c))),
+            }
+        };
+        tokens.push(Spanned { token, column });
+    }
+    Ok(tokens)
+}
+
+/// An evaluated expression. `value` is `None` in the first pass when it uses
+/// a label that has not been defined yet.
+#[derive(Debug, Clone, Copy)]
+struct Value {
+    value: Option<i64>,
+    column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Index {
+    X,
+    Y,
+}
+
+enum Operand {
+    Implied,
+    Accumulator,
+    Immediate(Value),
+    Direct(Value, Option<Index>),
+    IndirectX(Value),
+    IndirectY(Value),
+    Indirect(Value),
+    /// BBRn/BBSn: a zero page address and a branch target.
+    Pair(Value, Value),
+}
+
+/// The tokens of one line, consumed left to right.
+struct Line<'a> {
+    tokens: &'a [Spanned],
+    pos: usize,
+    number: usize,
+    end_column: usize,
+}
+
+impl Line<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end_column, |spanned| spanned.column)
+    }
+
+    fn error<T>(&self, column: usize, message: String) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.number,
+            column,
+            message,
+        })
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), AsmError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(self.column(), format!("expected `{}`", punct))
+        }
+    }
+
+    fn index(&mut self) -> Option<Index> {
+        let index = match self.peek() {
+            Some(Token::Ident(name)) if name.eq_ignore_ascii_case("x") => Index::X,
+            Some(Token::Ident(name)) if name.eq_ignore_ascii_case("y") => Index::Y,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(index)
+    }
+
+    fn end(&self) -> Result<(), AsmError> {
+        if self.pos < self.tokens.len() {
+            self.error(self.column(), "expected end of line".to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Assembler {
+    table: &'static [OpCode; 256],
+    symbols: HashMap<String, i64>,
+    /// Whether each operand whose size depended on its value was given two
+    /// bytes in the first pass, so both passes lay out code the same way.
+    wide: Vec<bool>,
+    output: Vec<(u16, u8)>,
+    pc: u32,
+    final_pass: bool,
+    operands: usize,
+}
+
+fn binary_precedence(token: &Token) -> Option<u8> {
+    match token {
+        Token::Punct("|") => Some(1),
+        Token::Punct("^") => Some(2),
+        Token::Punct("&") => Some(3),
+        Token::Punct("<<") | Token::Punct(">>") => Some(4),
+        Token::Punct("+") | Token::Punct("-") => Some(5),
+        Token::Punct("*") | Token::Punct("/") => Some(6),
+        _ => None,
+    }
+}
+
+impl Assembler {
+    fn pass(&mut self, source: &str) -> Result<(), AsmError> {
+        self.pc = DEFAULT_ORIGIN as u32;
+        self.operands = 0;
+        self.output.clear();
+        for (i, text) in source.lines().enumerate() {
+            let tokens = lex(text, i + 1)?;
+            let mut line = Line {
+                tokens: &tokens,
+                pos: 0,
+                number: i + 1,
+                end_column: text.chars().count() + 1,
+            };
+            self.statement(&mut line)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, line: &mut Line) -> Result<(), AsmError> {
+        // `name:` labels the current address, `name = expr` defines a constant
+        if let (Some(Token::Ident(name)), Some(Token::Punct(punct))) =
+            (line.peek().cloned(), line.tokens.get(1).map(|spanned| &spanned.token))
+        {
+            let column = line.column();
+            if *punct == ":" {
+                line.pos += 2;
+                self.define(line, &name, column, Some(self.pc as i64))?;
+            } else if *punct == "=" {
+                line.pos += 2;
+                let value = self.expression(line)?;
+                line.end()?;
+                return self.define(line, &name, column, value.value);
+            }
+        }
+
+        let column = line.column();
+        match line.next() {
+            None => Ok(()),
+            Some(Token::Ident(name)) if name.starts_with('.') => self.directive(line, &name, column),
+            Some(Token::Ident(name)) => self.instruction(line, &name.to_ascii_uppercase(), column),
+            Some(_) => line.error(column, "expected a label, directive or instruction".to_string()),
+        }
+    }
+
+    fn define(&mut self, line: &Line, name: &str, column: usize, value: Option<i64>) -> Result<(), AsmError> {
+        if ["A", "X", "Y"].iter().any(|register| name.eq_ignore_ascii_case(register)) || name.starts_with('.') {
+            return line.error(column, format!("`{}` cannot be used as a label", name));
+        }
+        if !self.final_pass && self.symbols.contains_key(name) {
+            return line.error(column, format!("`{}` is already defined", name));
+        }
+        if let Some(value) = value {
+            self.symbols.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, line: &mut Line, name: &str, column: usize) -> Result<(), AsmError> {
+        match name.to_ascii_lowercase().as_str() {
+            ".org" => {
+                let value = self.expression(line)?;
+                match value.value {
+                    Some(origin @ 0..=0xffff) => self.pc = origin as u32,
+                    Some(_) => return line.error(value.column, "origin must be in $0000-$FFFF".to_string()),
+                    None => return line.error(value.column, "`.org` needs a value known at this point".to_string()),
+                }
+            }
+            ".byte" | ".db" => loop {
+                if let Some(Token::Str(text)) = line.peek().cloned() {
+                    line.pos += 1;
+                    for byte in text {
+                        self.emit(line, byte)?;
+                    }
+                } else {
+                    let value = self.expression(line)?;
+                    let byte = self.byte(line, value)?;
+                    self.emit(line, byte)?;
+                }
+                if !line.eat(",") {
+                    break;
+                }
+            },
+            ".word" | ".dw" => loop {
+                let value = self.expression(line)?;
+                let word = self.word(line, value)?;
+                self.emit(line, word as u8)?;
+                self.emit(line, (word >> 8) as u8)?;
+                if !line.eat(",") {
+                    break;
+                }
+            },
+            _ => return line.error(column, format!("unknown directive `{}`", name)),
+        }
+        line.end()
+    }
+
+    fn emit(&mut self, line: &Line, byte: u8) -> Result<(), AsmError> {
+        if self.pc > 0xffff {
+            return line.error(line.column(), "code runs past $FFFF".to_string());
+        }
+        if self.final_pass {
+            self.output.push((self.pc as u16, byte));
+        }
+        self.pc += 1;
+        Ok(())
+    }
+
+    /// A value that must fit in one byte, signed or unsigned.
+    fn byte(&self, line: &Line, value: Value) -> Result<u8, AsmError> {
+        match value.value {
+            Some(byte @ -0x80..=0xff) => Ok(byte as u8),
+            Some(other) => line.error(value.column, format!("value {} does not fit in a byte", other)),
+            None => Ok(0),
+        }
+    }
+
+    fn word(&self, line: &Line, value: Value) -> Result<u16, AsmError> {
+        match value.value {
+            Some(word @ -0x8000..=0xffff) => Ok(word as u16),
+            Some(other) => line.error(value.column, format!("value {} does not fit in a word", other)),
+            None => Ok(0),
+        }
+    }
+
+    fn zero_page(&self, line: &Line, value: Value) -> Result<u8, AsmError> {
+        match value.value {
+            Some(address @ 0..=0xff) => Ok(address as u8),
+            Some(other) => line.error(value.column, format!("address ${:X} is not in zero page", other)),
+            None => Ok(0),
+        }
+    }
+
+    /// The signed offset from the end of the instruction to `target`.
+    fn branch_offset(&self, line: &Line, target: Value, len: u32) -> Result<u8, AsmError> {
+        let offset = match target.value {
+            Some(address) => address - (self.pc + len) as i64,
+            None => return Ok(0),
+        };
+        if (-128..=127).contains(&offset) {
+            Ok(offset as u8)
+        } else {
+            line.error(target.column, format!("branch target is {} bytes away, out of range", offset))
+        }
+    }
+
+    fn expression(&mut self, line: &mut Line) -> Result<Value, AsmError> {
+        self.binary(line, 1)
+    }
+
+    fn binary(&mut self, line: &mut Line, min_precedence: u8) -> Result<Value, AsmError> {
+        let mut left = self.unary(line)?;
+        while let Some(precedence) = line.peek().and_then(binary_precedence) {
+            if precedence < min_precedence {
+                break;
+            }
+            let operator = line.next();
+            let right = self.binary(line, precedence + 1)?;
+            let value = match (left.value, right.value) {
+                (Some(a), Some(b)) => Some(match operator {
+                    Some(Token::Punct("|")) => a | b,
+                    Some(Token::Punct("^")) => a ^ b,
+                    Some(Token::Punct("&")) => a & b,
+                    Some(Token::Punct("<<")) => a.wrapping_shl(b as u32),
+                    Some(Token::Punct(">>")) => a.wrapping_shr(b as u32),
+                    Some(Token::Punct("+")) => a.wrapping_add(b),
+                    Some(Token::Punct("-")) => a.wrapping_sub(b),
+                    Some(Token::Punct("*")) => a.wrapping_mul(b),
+                    _ if b == 0 => return line.error(right.column, "division by zero".to_string()),
+                    _ => match a.checked_div(b) {
+                        Some(quotient) => quotient,
+                        None => return line.error(left.column, "division overflows".to_string()),
+                    },
+                }),
+                _ => None,
+            };
+            left = Value {
+                value,
+                column: left.column,
+            };
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self, line: &mut Line) -> Result<Value, AsmError> {
+        let column = line.column();
+        let value = match line.next() {
+            Some(Token::Number(number)) => Some(number),
+            Some(Token::Ident(name)) => match self.symbols.get(&name) {
+                Some(&value) => Some(value),
+                None if self.final_pass => return line.error(column, format!("undefined label `{}`", name)),
+                None => None,
+            },
+            Some(Token::Punct("*")) => Some(self.pc as i64),
+            Some(Token::Punct("(")) => {
+                let inner = self.expression(line)?;
+                line.expect(")")?;
+                inner.value
+            }
+            Some(Token::Punct(operator @ ("-" | "<" | ">"))) => {
+                let operand = self.unary(line)?;
+                match (operator, operand.value) {
+                    (_, None) => None,
+                    ("-", Some(value)) => match value.checked_neg() {
+                        Some(negated) => Some(negated),
+                        None => return line.error(operand.column, "negation overflows".to_string()),
+                    },
+                    ("<", Some(value)) => Some(value & 0xff),
+                    (_, Some(value)) => Some((value >> 8) & 0xff),
+                }
+            }
+            _ => return line.error(column, "expected an expression".to_string()),
+        };
+        Ok(Value { value, column })
+    }
+
+    fn operand(&mut self, line: &mut Line) -> Result<Operand, AsmError> {
+        if line.peek().is_none() {
+            return Ok(Operand::Implied);
+        }
+        if let (Some(Token::Ident(name)), 1) = (line.peek(), line.tokens.len() - line.pos) {
+            if name.eq_ignore_ascii_case("a") {
+                line.pos += 1;
+                return Ok(Operand::Accumulator);
+            }
+        }
+        if line.eat("#") {
+            return Ok(Operand::Immediate(self.expression(line)?));
+        }
+        if line.eat("(") {
+            let value = self.expression(line)?;
+            if line.eat(",") {
+                if line.index() != Some(Index::X) {
+                    return line.error(line.column(), "expected `X`".to_string());
+                }
+                line.expect(")")?;
+                return Ok(Operand::IndirectX(value));
+            }
+            line.expect(")")?;
+            if line.eat(",") {
+                if line.index() != Some(Index::Y) {
+                    return line.error(line.column(), "expected `Y`".to_string());
+                }
+                return Ok(Operand::IndirectY(value));
+            }
+            return Ok(Operand::Indirect(value));
+        }
+
+        let value = self.expression(line)?;
+        if !line.eat(",") {
+            return Ok(Operand::Direct(value, None));
+        }
+        match line.index() {
+            Some(index) => Ok(Operand::Direct(value, Some(index))),
+            None => Ok(Operand::Pair(value, self.expression(line)?)),
+        }
+    }
+
+    /// The opcode for `mnemonic` matching `accept`, preferring official ones.
+    fn find(&self, mnemonic: &str, accept: impl Fn(&OpCode) -> bool) -> Option<&'static OpCode> {
+        self.table
+            .iter()
+            .filter(|opcode| opcode.mnemonic == mnemonic && accept(opcode))
+            .min_by_key(|opcode| opcode.unofficial)
+    }
+
+    fn find_mode(&self, mnemonic: &str, mode: AddressingMode) -> Option<&'static OpCode> {
+        self.find(mnemonic, |opcode| opcode.mode == mode)
+    }
+
+    /// Picks zero page or absolute addressing. Values unknown in the first
+    /// pass get absolute, and the choice sticks for the second pass.
+    fn is_wide(&mut self, value: Value, zero_page: Option<&OpCode>, absolute: Option<&OpCode>) -> bool {
+        match (zero_page, absolute) {
+            (Some(_), Some(_)) => {}
+            (None, _) => return true,
+            (_, None) => return false,
+        }
+        if self.final_pass {
+            self.operands += 1;
+            return self.wide[self.operands - 1];
+        }
+        let wide = !matches!(value.value, Some(0..=0xff));
+        self.wide.push(wide);
+        wide
+    }
+
+    fn instruction(&mut self, line: &mut Line, mnemonic: &str, column: usize) -> Result<(), AsmError> {
+        if self.find(mnemonic, |_| true).is_none() {
+            return line.error(column, format!("unknown instruction `{}`", mnemonic));
+        }
+        let operand_column = line.column();
+        let operand = self.operand(line)?;
+        line.end()?;
+        let unsupported = || AsmError {
+            line: line.number,
+            column: operand_column,
+            message: format!("`{}` does not support this addressing mode", mnemonic),
+        };
+        let implied = |opcode: &OpCode| opcode.mode == AddressingMode::NoneAddressing && opcode.len == 1;
+        // JSR, BBRn/BBSn and the 65C02's JMP ($nnnn,X) have no mode of their own
+        let special = |opcode: &OpCode| opcode.mode == AddressingMode::NoneAddressing && opcode.len == 3;
+
+        match operand {
+            Operand::Implied => {
+                let opcode = self
+                    .find(mnemonic, implied)
+                    .or_else(|| self.find_mode(mnemonic, AddressingMode::Accumulator))
+                    .ok_or_else(|| AsmError {
+                        message: format!("`{}` needs an operand", mnemonic),
+                        ..unsupported()
+                    })?;
+                self.emit(line, opcode.code)
+            }
+            Operand::Accumulator => {
+                let opcode = self.find_mode(mnemonic, AddressingMode::Accumulator).ok_or_else(unsupported)?;
+                self.emit(line, opcode.code)
+            }
+            Operand::Immediate(value) => {
+                let opcode = self.find_mode(mnemonic, AddressingMode::Immediate).ok_or_else(unsupported)?;
+                let byte = self.byte(line, value)?;
+                self.emit(line, opcode.code)?;
+                self.emit(line, byte)
+            }
+            Operand::Direct(value, None) if self.find_mode(mnemonic, AddressingMode::Relative).is_some() => {
+                let opcode = self.find_mode(mnemonic, AddressingMode::Relative).ok_or_else(unsupported)?;
+                let offset = self.branch_offset(line, value, 2)?;
+                self.emit(line, opcode.code)?;
+                self.emit(line, offset)
+            }
+            Operand::Direct(value, None) if mnemonic == "JSR" => {
+                let opcode = self.find(mnemonic, special).ok_or_else(unsupported)?;
+                self.emit_word(line, opcode, value)
+            }
+            Operand::Direct(value, index) => {
+                let (zero_page, absolute) = match index {
+                    None => (AddressingMode::ZeroPage, AddressingMode::Absolute),
+                    Some(Index::X) => (AddressingMode::ZeroPageX, AddressingMode::AbsoluteX),
+                    Some(Index::Y) => (AddressingMode::ZeroPageY, AddressingMode::AbsoluteY),
+                };
+                let zero_page = self.find_mode(mnemonic, zero_page);
+                let absolute = self.find_mode(mnemonic, absolute);
+                if zero_page.is_none() && absolute.is_none() {
+                    return Err(unsupported());
+                }
+                if self.is_wide(value, zero_page, absolute) {
+                    self.emit_word(line, absolute.ok_or_else(unsupported)?, value)
+                } else {
+                    self.emit_byte(line, zero_page.ok_or_else(unsupported)?, value)
+                }
+            }
+            Operand::IndirectX(value) => match self.find_mode(mnemonic, AddressingMode::IndirectX) {
+                Some(opcode) => self.emit_byte(line, opcode, value),
+                None => {
+                    let opcode = self
+                        .find(mnemonic, |opcode| special(opcode) && opcode.instruction == Instruction::JMP)
+                        .ok_or_else(unsupported)?;
+                    self.emit_word(line, opcode, value)
+                }
+            },
+            Operand::IndirectY(value) => {
+                let opcode = self.find_mode(mnemonic, AddressingMode::IndirectY).ok_or_else(unsupported)?;
+                self.emit_byte(line, opcode, value)
+            }
+            Operand::Indirect(value) => match self.find_mode(mnemonic, AddressingMode::Indirect) {
+                Some(opcode) => self.emit_word(line, opcode, value),
+                None => {
+                    let opcode = self
+                        .find_mode(mnemonic, AddressingMode::ZeroPageIndirect)
+                        .ok_or_else(unsupported)?;
+                    self.emit_byte(line, opcode, value)
+                }
+            },
+            Operand::Pair(address, target) => {
+                let opcode = self
+                    .find(mnemonic, |opcode| {
+                        special(opcode) && matches!(opcode.instruction, Instruction::BBR | Instruction::BBS)
+                    })
+                    .ok_or_else(unsupported)?;
+                let address = self.zero_page(line, address)?;
+                let offset = self.branch_offset(line, target, 3)?;
+                self.emit(line, opcode.code)?;
+                self.emit(line, address)?;
+                self.emit(line, offset)
+            }
+        }
+    }
+
+    /// Emits an opcode with a zero page operand.
+    fn emit_byte(&mut self, line: &Line, opcode: &OpCode, value: Value) -> Result<(), AsmError> {
+        let address = self.zero_page(line, value)?;
+        self.emit(line, opcode.code)?;
+        self.emit(line, address)
+    }
+
+    /// Emits an opcode with a 16-bit operand.
+    fn emit_word(&mut self, line: &Line, opcode: &OpCode, value: Value) -> Result<(), AsmError> {
+        let word = match value.value {
+            Some(word @ 0..=0xffff) => word as u16,
+            Some(other) => return line.error(value.column, format!("address {} is out of range", other)),
+            None => 0,
+        };
+        self.emit(line, opcode.code)?;
+        self.emit(line, word as u8)?;
+        self.emit(line, (word >> 8) as u8)
+    }
+}
@@ -0,0 +1,183 @@
+use std::any::Any;
+use std::ops::RangeInclusive;
+
+/// The CPU's view of the address space. Each machine supplies its own memory map.
+///
+/// `read` takes `&mut self` because reading a hardware register can have side
+/// effects (clearing a status flag, advancing a FIFO, ...). `peek` must not.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+
+    fn write(&mut self, address: u16, data: u8);
+
+    /// Reads a byte without side effects, for debuggers and tracers.
+    fn peek(&self, address: u16) -> u8;
+
+    /// Returns true once for each NMI edge a device on the bus has raised.
+    /// The CPU asks before every instruction and again just before a BRK or
+    /// IRQ fetches its vector, where a new NMI takes the vector over.
+    fn take_nmi(&mut self) -> bool {
+        false
+    }
+
+    fn read_u16(&mut self, position: u16) -> u16 {
+        let lo = self.read(position) as u16;
+        let hi = self.read(position.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn write_u16(&mut self, position: u16, data: u16) {
+        let hi = (data >> 8) as u8;
+        let lo = (data & 0xff) as u8;
+        self.write(position, lo);
+        self.write(position.wrapping_add(1), hi);
+    }
+}
+
+/// 64 KiB of plain RAM covering the whole address space, $0000-$FFFF.
+pub struct FlatRam {
+    memory: Box<[u8; 0x10000]>,
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        FlatRam {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+}
+
+impl Bus for FlatRam {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+}
+
+/// A device mapped into part of the address space by an [`IoBus`], such as an
+/// output port, a random number byte or a timer. Handlers keep their own state.
+pub trait IoHandler: Any {
+    /// Returns the byte a read sees, or `None` to leave it to the next handler.
+    fn read(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    /// Observes a write. Writes always reach memory as well.
+    fn write(&mut self, _address: u16, _data: u8) {}
+
+    /// Like `read`, without side effects.
+    fn peek(&self, _address: u16) -> Option<u8> {
+        None
+    }
+}
+
+/// Identifies a handler mapped into an [`IoBus`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId(usize);
+
+struct Mapping {
+    id: HandlerId,
+    range: RangeInclusive<u16>,
+    handler: Box<dyn IoHandler>,
+}
+
+/// Wraps a bus with I/O handlers on address ranges.
+///
+/// Handlers are checked in the order they were mapped. A read goes to the first
+/// handler covering the address that returns a value, or to the inner bus if
+/// none does. A write goes to every handler covering the address, in order, and
+/// then to the inner bus.
+pub struct IoBus<B: Bus = FlatRam> {
+    pub inner: B,
+    mappings: Vec<Mapping>,
+    next_id: usize,
+}
+
+impl Default for IoBus<FlatRam> {
+    fn default() -> Self {
+        IoBus::new(FlatRam::new())
+    }
+}
+
+impl<B: Bus> IoBus<B> {
+    pub fn new(inner: B) -> Self {
+        IoBus {
+            inner,
+            mappings: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Maps `handler` over `range`, after any handlers already mapped.
+    pub fn map<H: IoHandler>(&mut self, range: RangeInclusive<u16>, handler: H) -> HandlerId {
+        let id = HandlerId(self.next_id);
+        self.next_id += 1;
+        self.mappings.push(Mapping {
+            id,
+            range,
+            handler: Box::new(handler),
+        });
+        id
+    }
+
+    /// Removes a handler, returning it.
+    pub fn unmap(&mut self, id: HandlerId) -> Option<Box<dyn IoHandler>> {
+        let index = self.mappings.iter().position(|mapping| mapping.id == id)?;
+        Some(self.mappings.remove(index).handler)
+    }
+
+    /// Borrows a handler to look at its state.
+    pub fn handler<H: IoHandler>(&self, id: HandlerId) -> Option<&H> {
+        let mapping = self.mappings.iter().find(|mapping| mapping.id == id)?;
+        (&*mapping.handler as &dyn Any).downcast_ref()
+    }
+
+    pub fn handler_mut<H: IoHandler>(&mut self, id: HandlerId) -> Option<&mut H> {
+        let mapping = self.mappings.iter_mut().find(|mapping| mapping.id == id)?;
+        (&mut *mapping.handler as &mut dyn Any).downcast_mut()
+    }
+}
+
+impl<B: Bus> Bus for IoBus<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let handled = self
+            .mappings
+            .iter_mut()
+            .filter(|mapping| mapping.range.contains(&address))
+            .find_map(|mapping| mapping.handler.read(address));
+        handled.unwrap_or_else(|| self.inner.read(address))
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        for mapping in self.mappings.iter_mut().filter(|mapping| mapping.range.contains(&address)) {
+            mapping.handler.write(address, data);
+        }
+        self.inner.write(address, data);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let handled = self
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.range.contains(&address))
+            .find_map(|mapping| mapping.handler.peek(address));
+        handled.unwrap_or_else(|| self.inner.peek(address))
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        self.inner.take_nmi()
+    }
+}
@@ -1,16 +1,237 @@
-use std::collections::HashMap;
-use crate::opcodes;
+use std::fmt;
+use crate::bus::{Bus, FlatRam};
+use crate::loader::{Image, LoadError, Segment};
+use crate::opcodes::{self, Access, Instruction};
 
-pub struct CPU {
+mod cycle;
+
+const STACK: u16 = 0x0100;
+const STACK_RESET: u8 = 0xfd;
+
+bitflags! {
+    /// # Status Register (P) http://wiki.nesdev.com/w/index.php/Status_flags
+    ///
+    ///  7 6 5 4 3 2 1 0
+    ///  N V _ B D I Z C
+    ///  | |   | | | | +--- Carry Flag
+    ///  | |   | | | +----- Zero Flag
+    ///  | |   | | +------- Interrupt Disable
+    ///  | |   | +--------- Decimal Mode (not used on NES)
+    ///  | |   +----------- Break Command
+    ///  | +--------------- Overflow Flag
+    ///  +----------------- Negative Flag
+    ///
+    /// B and bit 5 have no storage in the real register; they only exist in
+    /// the byte pushed by PHP, BRK and interrupts.
+    pub struct CpuFlags: u8 {
+        const CARRY             = 0b0000_0001;
+        const ZERO              = 0b0000_0010;
+        const INTERRUPT_DISABLE = 0b0000_0100;
+        const DECIMAL           = 0b0000_1000;
+        const BREAK             = 0b0001_0000;
+        const UNUSED            = 0b0010_0000;
+        const OVERFLOW          = 0b0100_0000;
+        const NEGATIVE          = 0b1000_0000;
+    }
+}
+
+impl CpuFlags {
+    /// The value of P after reset: interrupts disabled, bit 5 reads as set.
+    pub const RESET: CpuFlags = CpuFlags::from_bits_truncate(0b0010_0100);
+
+    pub fn carry(&self) -> bool {
+        self.contains(CpuFlags::CARRY)
+    }
+
+    pub fn zero(&self) -> bool {
+        self.contains(CpuFlags::ZERO)
+    }
+
+    pub fn interrupt_disable(&self) -> bool {
+        self.contains(CpuFlags::INTERRUPT_DISABLE)
+    }
+
+    pub fn decimal(&self) -> bool {
+        self.contains(CpuFlags::DECIMAL)
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.contains(CpuFlags::OVERFLOW)
+    }
+
+    pub fn negative(&self) -> bool {
+        self.contains(CpuFlags::NEGATIVE)
+    }
+
+    /// Decodes a byte pulled by PLP or RTI. B is dropped and bit 5 always reads as set.
+    pub fn from_pushed_byte(data: u8) -> Self {
+        let mut flags = CpuFlags::from_bits_truncate(data);
+        flags.remove(CpuFlags::BREAK);
+        flags.insert(CpuFlags::UNUSED);
+        flags
+    }
+
+    /// Encodes the byte pushed on the stack. B is set for PHP and BRK, clear for NMI and IRQ.
+    pub fn to_pushed_byte(self, brk: bool) -> u8 {
+        let mut flags = self | CpuFlags::UNUSED;
+        flags.set(CpuFlags::BREAK, brk);
+        flags.bits()
+    }
+}
+
+pub mod interrupt {
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub enum InterruptType {
+        NMI,
+        IRQ,
+        BRK,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+    pub struct Interrupt {
+        pub itype: InterruptType,
+        pub vector_addr: u16,
+        pub break_flag: bool,
+        pub cpu_cycles: u8,
+    }
+
+    pub const NMI: Interrupt = Interrupt {
+        itype: InterruptType::NMI,
+        vector_addr: super::Vector::Nmi.address(),
+        break_flag: false,
+        cpu_cycles: 7,
+    };
+
+    pub const IRQ: Interrupt = Interrupt {
+        itype: InterruptType::IRQ,
+        vector_addr: super::Vector::Irq.address(),
+        break_flag: false,
+        cpu_cycles: 7,
+    };
+
+    pub const BRK: Interrupt = Interrupt {
+        itype: InterruptType::BRK,
+        vector_addr: super::Vector::Irq.address(),
+        break_flag: true,
+        cpu_cycles: 7,
+    };
+
+    /// IRQ line sources. The line is wired-OR, so it stays asserted while any source holds it.
+    pub const IRQ_EXTERNAL: u8 = 0b0000_0001;
+    pub const IRQ_APU_FRAME: u8 = 0b0000_0010;
+    pub const IRQ_APU_DMC: u8 = 0b0000_0100;
+    pub const IRQ_MAPPER: u8 = 0b0000_1000;
+}
+
+/// The addresses at the top of memory that hold where each interrupt, and
+/// reset, jumps to. BRK shares the IRQ vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vector {
+    Nmi,
+    Reset,
+    Irq,
+}
+
+impl Vector {
+    pub const fn address(self) -> u16 {
+        match self {
+            Vector::Nmi => 0xfffa,
+            Vector::Reset => 0xfffc,
+            Vector::Irq => 0xfffe,
+        }
+    }
+}
+
+/// Which member of the 6502 family the core behaves as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuVariant {
+    /// The original NMOS 6502, with decimal mode and its undocumented flag results.
+    Nmos6502,
+    /// The NES CPU: an NMOS 6502 with the decimal mode circuitry disconnected.
+    Ricoh2A03,
+    /// The CMOS 65C02 (Rockwell/WDC instruction set, without the WDC-only WAI and STP).
+    Cmos65C02,
+}
+
+impl CpuVariant {
+    fn has_decimal_mode(self) -> bool {
+        self != CpuVariant::Ricoh2A03
+    }
+}
+
+/// What a single call to `CPU::step` did.
+#[derive(Debug, Clone, Copy)]
+pub struct StepResult {
+    pub opcode: &'static opcodes::OpCode,
+    /// The effective address the instruction operated on, if its mode has one.
+    pub operand_address: Option<u16>,
+    /// Cycles used, including servicing an interrupt before the instruction.
+    pub cycles: u8,
+    /// The NMI or IRQ serviced before the instruction ran, if any.
+    pub interrupt: Option<interrupt::InterruptType>,
+}
+
+/// Why the CPU could not execute an instruction. `pc` is the address of the
+/// offending opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode has no meaning on this CPU variant.
+    UnknownOpcode { pc: u16, opcode: u8 },
+    /// A JAM (KIL) opcode halted the CPU; only a reset recovers it.
+    Jam { pc: u16, opcode: u8 },
+    /// An instruction asked for an operand its addressing mode does not have.
+    InvalidAddressing {
+        pc: u16,
+        opcode: u8,
+        mode: AddressingMode,
+    },
+    /// `tick` was called on a variant whose cycle timing is not modelled.
+    NoCycleStepping { variant: CpuVariant },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::Jam { pc, opcode } => {
+                write!(f, "CPU jammed by opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::InvalidAddressing { pc, opcode, mode } => write!(
+                f,
+                "opcode ${:02X} at ${:04X} has no operand in {:?} mode",
+                opcode, pc, mode
+            ),
+            CpuError::NoCycleStepping { variant } => {
+                write!(f, "cycle stepping is not modelled for the {:?}", variant)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+pub struct CPU<B: Bus = FlatRam> {
+    pub variant: CpuVariant,
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
-    pub status: u8,
+    pub status: CpuFlags,
     pub program_counter: u16,
-    memory: [u8; 0xFFFF]
+    pub stack_pointer: u8,
+    /// CPU cycles elapsed since the last reset, counting the reset sequence
+    /// itself, so the first opcode fetch happens at cycle 7 as in nestest.log.
+    pub cycles: u64,
+    pub bus: B,
+    nmi_pending: bool,
+    irq_line: u8,
+    irq_inhibit: bool,
+    extra_cycles: u8,
+    cycle_state: cycle::CycleState,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddressingMode {
     Immediate,
     ZeroPage,
@@ -21,48 +242,68 @@ pub enum AddressingMode {
     AbsoluteY,
     IndirectX,
     IndirectY,
+    /// `($nn)`, 65C02 only
+    ZeroPageIndirect,
+    /// Branch target: a signed offset from the next instruction.
+    Relative,
+    /// `JMP ($nnnn)`
+    Indirect,
+    Accumulator,
     NoneAddressing,
 }
 
-trait Mem {
-    fn mem_read(&self, address: u16) -> u8; 
+fn page_cross(from: u16, to: u16) -> bool {
+    from & 0xFF00 != to & 0xFF00
+}
 
-    fn mem_write(&mut self, address: u16, data: u8);
-    
-    fn mem_read_u16(&self, position: u16) -> u16 {
-        let lo = self.mem_read(position) as u16;
-        let hi = self.mem_read(position + 1) as u16;
-        (hi << 8) | (lo as u16)
-    }
+/// SHA, SHX, SHY and TAS store `data & (H + 1)`, where H is the high byte of the
+/// unindexed address. When indexing crosses a page the stored value also replaces
+/// the high byte of the target address.
+fn store_and_high_byte(address: u16, page_crossed: bool, index: u8, data: u8) -> (u16, u8) {
+    let base = address.wrapping_sub(index as u16);
+    let value = data & ((base >> 8) as u8).wrapping_add(1);
 
-    fn mem_write_u16(&mut self, position: u16, data: u16) {
-        let hi = (data >> 8) as u8;
-        let lo = (data & 0xff) as u8;
-        self.mem_write(position, lo);
-        self.mem_write(position + 1, hi);
+    let address = if page_crossed {
+        ((value as u16) << 8) | (address & 0x00ff)
+    } else {
+        address
+    };
+    (address, value)
+}
+
+impl Default for CPU<FlatRam> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl Mem for CPU {
-    
-    fn mem_read(&self, address: u16) -> u8 { 
-        self.memory[address as usize]
+impl CPU<FlatRam> {
+    pub fn new() -> Self {
+        CPU::with_variant(CpuVariant::Ricoh2A03)
     }
 
-    fn mem_write(&mut self, address: u16, data: u8) { 
-        self.memory[address as usize] = data;
+    pub fn with_variant(variant: CpuVariant) -> Self {
+        CPU::with_bus(variant, FlatRam::new())
     }
 }
 
-impl CPU {
-    pub fn new() -> Self {
+impl<B: Bus> CPU<B> {
+    pub fn with_bus(variant: CpuVariant, bus: B) -> Self {
         CPU {
+            variant,
             register_a: 0,
             register_x: 0,
             register_y: 0,
-            status: 0,
+            status: CpuFlags::RESET,
             program_counter: 0,
-            memory: [0; 0xFFFF]
+            stack_pointer: STACK_RESET,
+            bus,
+            nmi_pending: false,
+            irq_line: 0,
+            irq_inhibit: true,
+            cycles: 0,
+            extra_cycles: 0,
+            cycle_state: Default::default(),
         }
     }
 
@@ -70,73 +311,419 @@ impl CPU {
         self.mem_write(address, data);
     }
 
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
+    /// Reads memory without triggering any side effects of the bus.
+    pub fn read_memory(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
 
-        match mode {
-            AddressingMode::Immediate => self.program_counter,
+    fn mem_read(&mut self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    fn mem_write(&mut self, address: u16, data: u8) {
+        self.bus.write(address, data);
+    }
+
+    fn mem_read_u16(&mut self, position: u16) -> u16 {
+        self.bus.read_u16(position)
+    }
+
+    fn mem_write_u16(&mut self, position: u16, data: u16) {
+        self.bus.write_u16(position, data);
+    }
+
+    /// The stack lives in page $01 and grows downwards; S points at the next free slot.
+    fn stack_push(&mut self, data: u8) {
+        self.mem_write(STACK + self.stack_pointer as u16, data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    fn stack_pop(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.mem_read(STACK + self.stack_pointer as u16)
+    }
+
+    fn stack_push_u16(&mut self, data: u16) {
+        self.stack_push((data >> 8) as u8);
+        self.stack_push((data & 0xff) as u8);
+    }
+
+    fn stack_pop_u16(&mut self) -> u16 {
+        let lo = self.stack_pop() as u16;
+        let hi = self.stack_pop() as u16;
+        (hi << 8) | lo
+    }
+
+    /// Resolves the effective address for `mode`, and whether indexing it crossed a page.
+    fn get_operand_address(&mut self, mode: &AddressingMode) -> Result<(u16, bool), CpuError> {
+
+        let resolved = match mode {
+            AddressingMode::Immediate => (self.program_counter, false),
+
+            AddressingMode::ZeroPage  => (self.mem_read(self.program_counter) as u16, false),
+
+            AddressingMode::Absolute => (self.mem_read_u16(self.program_counter), false),
 
-            AddressingMode::ZeroPage  => self.mem_read(self.program_counter) as u16,
-            
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
-          
             AddressingMode::ZeroPageX => {
                 let position = self.mem_read(self.program_counter);
-                let address = position.wrapping_add(self.register_x) as u16;
-                address
+                (position.wrapping_add(self.register_x) as u16, false)
             }
             AddressingMode::ZeroPageY => {
                 let position = self.mem_read(self.program_counter);
-                let address = position.wrapping_add(self.register_y) as u16;
-                address
+                (position.wrapping_add(self.register_y) as u16, false)
             }
 
             AddressingMode::AbsoluteX => {
                 let base = self.mem_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_x as u16);
-                address
+                (address, page_cross(base, address))
             }
             AddressingMode::AbsoluteY => {
                 let base = self.mem_read_u16(self.program_counter);
                 let address = base.wrapping_add(self.register_y as u16);
-                address
+                (address, page_cross(base, address))
             }
 
             AddressingMode::IndirectX => {
                 let base = self.mem_read(self.program_counter);
 
-                let pointer: u8 = (base as u8).wrapping_add(self.register_x);
+                let pointer: u8 = base.wrapping_add(self.register_x);
                 let lo = self.mem_read(pointer as u16);
                 let hi = self.mem_read(pointer.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
+                ((hi as u16) << 8 | (lo as u16), false)
             }
             AddressingMode::IndirectY => {
                 let base = self.mem_read(self.program_counter);
 
                 let lo = self.mem_read(base as u16);
-                let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
+                let hi = self.mem_read(base.wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
                 let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+                (deref, page_cross(deref_base, deref))
+            }
+            AddressingMode::ZeroPageIndirect => {
+                let base = self.mem_read(self.program_counter);
+
+                let lo = self.mem_read(base as u16);
+                let hi = self.mem_read(base.wrapping_add(1) as u16);
+                ((hi as u16) << 8 | (lo as u16), false)
+            }
+
+            AddressingMode::Relative => {
+                let offset = self.mem_read(self.program_counter) as i8;
+                let next = self.program_counter.wrapping_add(1);
+                let target = next.wrapping_add(offset as u16);
+                (target, page_cross(next, target))
+            }
+            AddressingMode::Indirect => {
+                let pointer = self.mem_read_u16(self.program_counter);
+                let target = if pointer & 0x00FF == 0x00FF && self.variant != CpuVariant::Cmos65C02 {
+                    // NMOS bug: the high byte is fetched from the start of the same page
+                    let lo = self.mem_read(pointer);
+                    let hi = self.mem_read(pointer & 0xFF00);
+                    (hi as u16) << 8 | (lo as u16)
+                } else {
+                    self.mem_read_u16(pointer)
+                };
+                (target, false)
+            }
+
+            AddressingMode::Accumulator | AddressingMode::NoneAddressing => {
+                let pc = self.program_counter.wrapping_sub(1);
+                return Err(CpuError::InvalidAddressing {
+                    pc,
+                    opcode: self.mem_read(pc),
+                    mode: *mode,
+                });
             }
-           
-            AddressingMode::NoneAddressing => {
-                panic!("mode {:?} is not supported", mode);
+        };
+
+        Ok(resolved)
+    }
+
+    /// Reads the operand of a read instruction. Those take one extra cycle when
+    /// indexing crosses a page; stores and read-modify-write ops always pay it in their base count.
+    fn read_operand(&mut self, mode: &AddressingMode) -> Result<(u16, u8), CpuError> {
+        let (address, page_crossed) = self.get_operand_address(mode)?;
+        if page_crossed {
+            self.extra_cycles += 1;
+        }
+        Ok((address, self.mem_read(address)))
+    }
+
+    /// Applies a read instruction to the byte its addressing mode fetched.
+    fn read_op(&mut self, opcode: &opcodes::OpCode, value: u8) {
+        match opcode.instruction {
+            Instruction::LDA => self.set_register_a(value),
+            Instruction::LDX => self.set_register_x(value),
+            Instruction::LDY => self.set_register_y(value),
+            Instruction::ADC => self.add_with_carry(value),
+            Instruction::SBC => self.subtract_with_borrow(value),
+            Instruction::AND => self.set_register_a(self.register_a & value),
+            Instruction::EOR => self.set_register_a(self.register_a ^ value),
+            Instruction::ORA => self.set_register_a(self.register_a | value),
+            Instruction::CMP => self.compare(self.register_a, value),
+            Instruction::CPX => self.compare(self.register_x, value),
+            Instruction::CPY => self.compare(self.register_y, value),
+            /* 65C02 BIT #imm only affects Z */
+            Instruction::BIT if opcode.mode == AddressingMode::Immediate => {
+                self.status.set(CpuFlags::ZERO, self.register_a & value == 0);
             }
+            Instruction::BIT => self.bit(value),
+            Instruction::LAX => self.lax(value),
+            Instruction::ANC => self.anc(value),
+            Instruction::ALR => self.alr(value),
+            Instruction::ARR => self.arr(value),
+            Instruction::AXS => self.axs(value),
+            Instruction::LAS => self.las(value),
+            Instruction::XAA => self.xaa(value),
+            Instruction::LXA => self.lxa(value),
+            /* NOPs that read their operand */
+            _ => {}
         }
+    }
 
+    /// Picks the address and the byte a store instruction writes.
+    fn write_op(&mut self, opcode: &opcodes::OpCode, address: u16, page_crossed: bool) -> (u16, u8) {
+        match opcode.instruction {
+            Instruction::STA => (address, self.register_a),
+            Instruction::STX => (address, self.register_x),
+            Instruction::STY => (address, self.register_y),
+            Instruction::STZ => (address, 0),
+            Instruction::SAX => (address, self.register_a & self.register_x),
+            Instruction::SHA => {
+                let data = self.register_a & self.register_x;
+                store_and_high_byte(address, page_crossed, self.register_y, data)
+            }
+            Instruction::SHX => store_and_high_byte(address, page_crossed, self.register_y, self.register_x),
+            Instruction::SHY => store_and_high_byte(address, page_crossed, self.register_x, self.register_y),
+            Instruction::TAS => {
+                self.stack_pointer = self.register_a & self.register_x;
+                store_and_high_byte(address, page_crossed, self.register_y, self.stack_pointer)
+            }
+            _ => unreachable!("{} is not a store", opcode.mnemonic),
+        }
     }
 
-    fn lda(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(&mode);
+    /// Computes the byte a read-modify-write instruction writes back, then applies
+    /// the second half of the unofficial combined ones to A.
+    fn modify_op(&mut self, opcode: &opcodes::OpCode, value: u8) -> u8 {
+        let result = match opcode.instruction {
+            Instruction::ASL | Instruction::SLO => self.asl(value),
+            Instruction::LSR | Instruction::SRE => self.lsr(value),
+            Instruction::ROL | Instruction::RLA => self.rol(value),
+            Instruction::ROR | Instruction::RRA => self.ror(value),
+            Instruction::INC | Instruction::ISB => value.wrapping_add(1),
+            Instruction::DEC | Instruction::DCP => value.wrapping_sub(1),
+            Instruction::TSB => return self.tsb(value),
+            Instruction::TRB => return self.trb(value),
+            /* RMBn, SMBn */
+            _ => {
+                let bit = (opcode.code >> 4) & 0x07;
+                return if opcode.code & 0x80 != 0 {
+                    value | (1 << bit)
+                } else {
+                    value & !(1 << bit)
+                };
+            }
+        };
+        self.update_zero_and_negative_flags(result);
+
+        match opcode.instruction {
+            Instruction::SLO => self.set_register_a(self.register_a | result),
+            Instruction::RLA => self.set_register_a(self.register_a & result),
+            Instruction::SRE => self.set_register_a(self.register_a ^ result),
+            Instruction::RRA => self.add_with_carry(result),
+            Instruction::DCP => self.compare(self.register_a, result),
+            Instruction::ISB => self.subtract_with_borrow(result),
+            _ => {}
+        }
+        result
+    }
+
+    fn asl(&mut self, data: u8) -> u8 {
+        self.set_carry_flag(data >> 7 == 1);
+        data << 1
+    }
+
+    fn lsr(&mut self, data: u8) -> u8 {
+        self.set_carry_flag(data & 1 == 1);
+        data >> 1
+    }
+
+    fn rol(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.carry() as u8;
+        self.set_carry_flag(data >> 7 == 1);
+        (data << 1) | old_carry
+    }
+
+    fn ror(&mut self, data: u8) -> u8 {
+        let old_carry = self.status.carry() as u8;
+        self.set_carry_flag(data & 1 == 1);
+        (data >> 1) | (old_carry << 7)
+    }
+
+    fn lax(&mut self, value: u8) {
+        self.set_register_a(value);
+        self.register_x = value;
+    }
+
+    fn anc(&mut self, value: u8) {
+        self.set_register_a(self.register_a & value);
+        self.set_carry_flag(self.status.negative());
+    }
+
+    fn alr(&mut self, value: u8) {
+        let result = self.lsr(self.register_a & value);
+        self.set_register_a(result);
+    }
+
+    /// AND followed by ROR A, except C comes from bit 6 and V from bit 6 XOR bit 5.
+    /// In decimal mode the NMOS 6502 then adjusts each nybble of the result the
+    /// way ADC would, and C reports whether the high one was adjusted. N and Z
+    /// still come from the unadjusted result.
+    /// http://www.oxyron.de/html/opcodes02.html
+    fn arr(&mut self, value: u8) {
+        let and = self.register_a & value;
+        let result = self.ror(and);
+        self.set_register_a(result);
+
+        let bit_6 = (result >> 6) & 1;
+        let bit_5 = (result >> 5) & 1;
+        self.set_overflow_flag(bit_6 ^ bit_5 == 1);
+        if !self.decimal_mode_active() {
+            self.set_carry_flag(bit_6 == 1);
+            return;
+        }
+
+        let mut adjusted = result;
+        if (and & 0x0f) + (and & 0x01) > 0x05 {
+            adjusted = (adjusted & 0xf0) | (adjusted.wrapping_add(0x06) & 0x0f);
+        }
+        let high_adjusted = (and as u16 & 0xf0) + (and as u16 & 0x10) > 0x50;
+        if high_adjusted {
+            adjusted = adjusted.wrapping_add(0x60);
+        }
+        self.register_a = adjusted;
+        self.set_carry_flag(high_adjusted);
+    }
+
+    /// X = (A & X) - M, setting C like CMP and ignoring the incoming carry.
+    fn axs(&mut self, value: u8) {
+        let and = self.register_a & self.register_x;
+
+        self.set_carry_flag(and >= value);
+        self.set_register_x(and.wrapping_sub(value));
+    }
+
+    fn las(&mut self, value: u8) {
+        let value = value & self.stack_pointer;
+        self.register_x = value;
+        self.stack_pointer = value;
+        self.set_register_a(value);
+    }
+
+    /// XAA and LXA mix in bus noise on real chips; 0xEE is the most commonly observed constant.
+    fn xaa(&mut self, value: u8) {
+        self.set_register_a((self.register_a | 0xee) & self.register_x & value);
+    }
+
+    fn lxa(&mut self, value: u8) {
+        self.set_register_a((self.register_a | 0xee) & value);
+        self.register_x = self.register_a;
+    }
+
+    fn compare(&mut self, compare_with: u8, value: u8) {
+        self.set_carry_flag(compare_with >= value);
+        self.update_zero_and_negative_flags(compare_with.wrapping_sub(value));
+    }
+
+    fn bit(&mut self, value: u8) {
+        self.status.set(CpuFlags::ZERO, self.register_a & value == 0);
+        self.status.set(CpuFlags::NEGATIVE, value & 0b1000_0000 != 0);
+        self.status.set(CpuFlags::OVERFLOW, value & 0b0100_0000 != 0);
+    }
+
+    fn tsb(&mut self, value: u8) -> u8 {
+        self.status.set(CpuFlags::ZERO, self.register_a & value == 0);
+        value | self.register_a
+    }
+
+    fn trb(&mut self, value: u8) -> u8 {
+        self.status.set(CpuFlags::ZERO, self.register_a & value == 0);
+        value & !self.register_a
+    }
+
+    /// Whether a conditional branch is taken, or None if `instruction` is not a branch.
+    fn branch_condition(&self, instruction: Instruction) -> Option<bool> {
+        let taken = match instruction {
+            Instruction::BPL => !self.status.negative(),
+            Instruction::BMI => self.status.negative(),
+            Instruction::BVC => !self.status.overflow(),
+            Instruction::BVS => self.status.overflow(),
+            Instruction::BCC => !self.status.carry(),
+            Instruction::BCS => self.status.carry(),
+            Instruction::BNE => !self.status.zero(),
+            Instruction::BEQ => self.status.zero(),
+            Instruction::BRA => true,
+            _ => return None,
+        };
+        Some(taken)
+    }
+
+    /// A taken branch costs one extra cycle, and one more if it lands on another page.
+    /// Returns the target and whether the branch was taken.
+    fn branch(&mut self, mode: &AddressingMode, condition: bool) -> Result<(u16, bool), CpuError> {
+        let (target, page_crossed) = self.get_operand_address(mode)?;
+        if condition {
+            self.extra_cycles += 1;
+            if page_crossed {
+                self.extra_cycles += 1;
+            }
+            self.program_counter = target;
+        }
+        Ok((target, condition))
+    }
+
+    fn jmp(&mut self, mode: &AddressingMode) -> Result<u16, CpuError> {
+        let (target, _) = self.get_operand_address(mode)?;
+        self.program_counter = target;
+        Ok(target)
+    }
+
+    /// BBRn/BBSn: test bit n of a zero page byte, then branch relative to the next instruction.
+    /// Returns whether the branch was taken.
+    fn branch_on_bit(&mut self, bit: u8, set: bool) -> bool {
+        let address = self.mem_read(self.program_counter) as u16;
         let value = self.mem_read(address);
+        let offset = self.mem_read(self.program_counter.wrapping_add(1)) as i8;
+        let taken = (value >> bit) & 1 == set as u8;
+        if taken {
+            let next = self.program_counter.wrapping_add(2);
+            let target = next.wrapping_add(offset as u16);
 
+            self.extra_cycles += 1;
+            if page_cross(next, target) {
+                self.extra_cycles += 1;
+            }
+            self.program_counter = target;
+        }
+        taken
+    }
+
+    fn set_register_a(&mut self, value: u8) {
         self.register_a = value;
         self.update_zero_and_negative_flags(self.register_a);
     }
 
-    fn sta(&mut self, mode: &AddressingMode) {
-        let address = self.get_operand_address(mode);
-        self.mem_write(address, self.register_a);
+    fn set_register_x(&mut self, value: u8) {
+        self.register_x = value;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn set_register_y(&mut self, value: u8) {
+        self.register_y = value;
+        self.update_zero_and_negative_flags(self.register_y);
     }
 
     fn tax(&mut self) {
@@ -144,74 +731,529 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_x);
     }
 
-    fn update_zero_and_negative_flags(&mut self, result: u8) {
-        if result == 0 {
-            self.status = self.status | 0b0000_0010;
+    fn tay(&mut self) {
+        self.register_y = self.register_a;
+        self.update_zero_and_negative_flags(self.register_y);
+    }
+
+    fn txa(&mut self) {
+        self.set_register_a(self.register_x);
+    }
+
+    fn tya(&mut self) {
+        self.set_register_a(self.register_y);
+    }
+
+    fn tsx(&mut self) {
+        self.register_x = self.stack_pointer;
+        self.update_zero_and_negative_flags(self.register_x);
+    }
+
+    fn txs(&mut self) {
+        self.stack_pointer = self.register_x;
+    }
+
+    fn pha(&mut self) {
+        self.stack_push(self.register_a);
+    }
+
+    fn pla(&mut self) {
+        let data = self.stack_pop();
+        self.set_register_a(data);
+    }
+
+    /// B and the unused bit have no storage in the status register; they only
+    /// appear in the copy that is pushed. PHP always pushes both as set.
+    fn php(&mut self) {
+        self.stack_push(self.status.to_pushed_byte(true));
+    }
+
+    fn plp(&mut self) {
+        let data = self.stack_pop();
+        self.status = CpuFlags::from_pushed_byte(data);
+    }
+
+    /// JSR pushes the address of its own last byte; RTS adds the missing one back.
+    /// The target's low byte is read before the push and its high byte after.
+    fn jsr(&mut self) -> u16 {
+        let lo = self.mem_read(self.program_counter);
+        self.stack_push_u16(self.program_counter.wrapping_add(1));
+        let hi = self.mem_read(self.program_counter.wrapping_add(1));
+        self.program_counter = (hi as u16) << 8 | lo as u16;
+        self.program_counter
+    }
+
+    fn rts(&mut self) {
+        self.program_counter = self.stack_pop_u16().wrapping_add(1);
+    }
+
+    fn rti(&mut self) {
+        let data = self.stack_pop();
+        self.status = CpuFlags::from_pushed_byte(data);
+        self.program_counter = self.stack_pop_u16();
+    }
+
+    /// Latches an NMI edge. It is serviced before the next instruction.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Latches an NMI edge raised by a device on the bus.
+    fn poll_bus_nmi(&mut self) {
+        if self.bus.take_nmi() {
+            self.nmi_pending = true;
+        }
+    }
+
+    /// Asserts or releases the IRQ line on behalf of one of the `interrupt::IRQ_*` sources.
+    pub fn set_irq_line(&mut self, source: u8, asserted: bool) {
+        if asserted {
+            self.irq_line |= source;
         } else {
-            self.status = self.status & 0b1111_1101;
+            self.irq_line &= !source;
         }
+    }
+
+    pub fn irq_asserted(&self) -> bool {
+        self.irq_line != 0
+    }
 
-        if result & 0b1000_0000 != 0 {
-            self.status = self.status | 0b1000_0000;
+    fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        if interrupt.itype == interrupt::InterruptType::NMI {
+            self.nmi_pending = false;
+        }
+
+        self.stack_push_u16(self.program_counter);
+        self.stack_push(self.status.to_pushed_byte(interrupt.break_flag));
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.status.remove(CpuFlags::DECIMAL);
+        }
+        self.irq_inhibit = true;
+
+        // An NMI that arrives while a BRK or IRQ is pushing its state hijacks
+        // the vector fetch. The pushed B flag still tells the handler it was a BRK.
+        self.poll_bus_nmi();
+        let vector_addr = if interrupt.itype != interrupt::InterruptType::NMI && self.nmi_pending {
+            self.nmi_pending = false;
+            interrupt::NMI.vector_addr
         } else {
-            self.status = self.status & 0b0111_1111;
+            interrupt.vector_addr
+        };
+        self.program_counter = self.mem_read_u16(vector_addr);
+    }
+
+    /// The interrupt to service before the next instruction: a pending NMI, or an
+    /// IRQ if the line is held and interrupts were enabled when the previous
+    /// instruction polled them.
+    fn pending_interrupt(&self) -> Option<interrupt::Interrupt> {
+        if self.nmi_pending {
+            Some(interrupt::NMI)
+        } else if self.irq_asserted() && !self.irq_inhibit {
+            Some(interrupt::IRQ)
+        } else {
+            None
         }
     }
 
-    fn inx(&mut self) {
-        self.register_x = self.register_x.wrapping_add(1);
-        self.update_zero_and_negative_flags(self.register_x);
+    fn update_zero_and_negative_flags(&mut self, result: u8) {
+        self.status.set(CpuFlags::ZERO, result == 0);
+        self.status.set(CpuFlags::NEGATIVE, result & 0b1000_0000 != 0);
+    }
+
+    fn set_carry_flag(&mut self, on: bool) {
+        self.status.set(CpuFlags::CARRY, on);
+    }
+
+    fn set_overflow_flag(&mut self, on: bool) {
+        self.status.set(CpuFlags::OVERFLOW, on);
+    }
+
+    /// Binary addition; also sets the flags binary SBC leaves behind in decimal mode.
+    /// http://www.righto.com/2012/12/the-6502-overflow-flag-explained.html
+    fn add_to_register_a(&mut self, data: u8) {
+        let carry_in = self.status.carry() as u16;
+        let sum = self.register_a as u16 + data as u16 + carry_in;
+
+        self.set_carry_flag(sum > 0xff);
+
+        let result = sum as u8;
+        self.set_overflow_flag((data ^ result) & (result ^ self.register_a) & 0x80 != 0);
+
+        self.set_register_a(result);
+    }
+
+    fn decimal_mode_active(&self) -> bool {
+        self.status.decimal() && self.variant.has_decimal_mode()
+    }
+
+    /// ADC, honouring decimal mode where the variant has one. The NMOS 6502 takes
+    /// Z from the binary sum and N/V from the half-adjusted intermediate; the 65C02
+    /// sets N and Z from the BCD result at the cost of one extra cycle.
+    /// http://www.6502.org/tutorials/decimal_mode.html
+    fn add_with_carry(&mut self, data: u8) {
+        if !self.decimal_mode_active() {
+            self.add_to_register_a(data);
+            return;
+        }
+
+        let a = self.register_a;
+        let carry_in = self.status.carry() as u16;
+        let binary = (a as u16 + data as u16 + carry_in) as u8;
+
+        let mut lo = (a & 0x0f) as u16 + (data & 0x0f) as u16 + carry_in;
+        if lo >= 0x0a {
+            lo = ((lo + 0x06) & 0x0f) + 0x10;
+        }
+        let signed = (a & 0xf0) as i8 as i16 + (data & 0xf0) as i8 as i16 + lo as i16;
+        let mut sum = (a & 0xf0) as u16 + (data & 0xf0) as u16 + lo;
+        let negative = sum & 0x80 != 0;
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+
+        self.set_carry_flag(sum > 0xff);
+        self.set_overflow_flag(!(-128..=127).contains(&signed));
+
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.set_register_a(sum as u8);
+            self.extra_cycles += 1;
+        } else {
+            self.register_a = sum as u8;
+            self.status.set(CpuFlags::ZERO, binary == 0);
+            self.status.set(CpuFlags::NEGATIVE, negative);
+        }
     }
 
-    pub fn load_and_run(&mut self, program: Vec<u8>) {
+    /// A - M - (1 - C) is the same as A + !M + C. In decimal mode C and V still come
+    /// from the binary subtraction; only the 65C02 recomputes N and Z from the result.
+    fn subtract_with_borrow(&mut self, data: u8) {
+        let a = self.register_a;
+        let borrow = 1 - self.status.carry() as i16;
+        self.add_to_register_a(!data);
+
+        if !self.decimal_mode_active() {
+            return;
+        }
+
+        let lo = (a & 0x0f) as i16 - (data & 0x0f) as i16 - borrow;
+        let result = if self.variant == CpuVariant::Cmos65C02 {
+            let mut result = a as i16 - data as i16 - borrow;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if lo < 0 {
+                result -= 0x06;
+            }
+            result
+        } else {
+            let lo = if lo < 0 { ((lo - 0x06) & 0x0f) - 0x10 } else { lo };
+            let mut result = (a & 0xf0) as i16 - (data & 0xf0) as i16 + lo;
+            if result < 0 {
+                result -= 0x60;
+            }
+            result
+        };
+
+        if self.variant == CpuVariant::Cmos65C02 {
+            self.set_register_a(result as u8);
+            self.extra_cycles += 1;
+        } else {
+            self.register_a = result as u8;
+        }
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
         self.load(program);
         self.reset();
         self.run()
     }
 
+    /// Copies `program` to $8000 and points the reset vector at it.
+    ///
+    /// # Panics
+    ///
+    /// If the program runs past $FFFF; `load_at` returns an error instead.
     pub fn load(&mut self, program: Vec<u8>) {
-        self.memory[0x8000 .. (0x8000 + program.len())].copy_from_slice(&program[..]);
-        self.mem_write_u16(0xFFFC, 0x8000);
+        if let Err(err) = self.load_at(0x8000, &program) {
+            panic!("{}", err);
+        }
+        self.set_vector(Vector::Reset, 0x8000);
+    }
+
+    /// Copies `bytes` to memory starting at `origin`. Nothing is written if
+    /// they would run past $FFFF.
+    pub fn load_at(&mut self, origin: u16, bytes: &[u8]) -> Result<(), LoadError> {
+        let segment = Segment::new(origin as u32, bytes.to_vec())?;
+        self.load_image(&Image {
+            segments: vec![segment],
+            start: None,
+        })
+    }
+
+    /// Copies every segment of `image` to memory, checking them all first.
+    /// Vectors are left alone.
+    pub fn load_image(&mut self, image: &Image) -> Result<(), LoadError> {
+        for segment in &image.segments {
+            if segment.end() > 0x10000 {
+                return Err(LoadError::DoesNotFit {
+                    origin: segment.origin as u32,
+                    len: segment.bytes.len(),
+                });
+            }
+        }
+        for segment in &image.segments {
+            for (i, &byte) in segment.bytes.iter().enumerate() {
+                self.mem_write(segment.origin + i as u16, byte);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_vector(&mut self, vector: Vector, target: u16) {
+        self.mem_write_u16(vector.address(), target);
+    }
+
+    pub fn vector(&self, vector: Vector) -> u16 {
+        let address = vector.address();
+        u16::from_le_bytes([self.bus.peek(address), self.bus.peek(address + 1)])
     }
 
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
-        self.status = 0;
+        self.status = CpuFlags::RESET;
+        self.stack_pointer = STACK_RESET;
+        self.irq_inhibit = true;
+        self.cycle_state = Default::default();
+        // the count restarts, and the reset sequence takes 7 cycles before the
+        // first opcode fetch
+        self.cycles = 7;
 
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.program_counter = self.mem_read_u16(Vector::Reset.address());
     }
 
-    pub fn run(&mut self) {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
+    /// Runs until a BRK instruction has been executed.
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        self.run_until(|_, step| step.opcode.code == 0x00)?;
+        Ok(())
+    }
 
+    /// Steps until `predicate` returns true for the CPU state after a step,
+    /// and returns that step.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<StepResult, CpuError>
+    where
+        F: FnMut(&CPU<B>, &StepResult) -> bool,
+    {
         loop {
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
+            let step = self.step()?;
+            if predicate(self, &step) {
+                return Ok(step);
+            }
+        }
+    }
 
-            let opcode = opcodes.get(&code).expect(&format!("OpCode {:x} is not recognized", code));
+    /// Steps until at least `cycles` cycles have elapsed. Instructions are not
+    /// split, so this can overshoot; returns the number of cycles actually run.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, CpuError> {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            self.step()?;
+        }
+        Ok(self.cycles - start)
+    }
 
-            match code {
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
-                    self.lda(&opcode.mode);
+    /// Runs a decoded instruction. PC points just past the opcode byte.
+    /// Returns the address the operand resolved to, if any, and whether the
+    /// instruction loaded PC itself.
+    fn execute(&mut self, opcode: &opcodes::OpCode) -> Result<(Option<u16>, bool), CpuError> {
+        let operand_address = match opcode.access {
+            Access::Read => {
+                let (address, value) = self.read_operand(&opcode.mode)?;
+                self.read_op(opcode, value);
+                address
+            }
+            Access::Write => {
+                let (operand_address, page_crossed) = self.get_operand_address(&opcode.mode)?;
+                let (address, value) = self.write_op(opcode, operand_address, page_crossed);
+                self.mem_write(address, value);
+                operand_address
+            }
+            Access::ReadModifyWrite => {
+                let (address, page_crossed) = self.get_operand_address(&opcode.mode)?;
+                // the 65C02 shifts on Absolute,X only pay for page crossings
+                if page_crossed
+                    && self.variant == CpuVariant::Cmos65C02
+                    && matches!(opcode.instruction, Instruction::ASL | Instruction::LSR | Instruction::ROL | Instruction::ROR)
+                {
+                    self.extra_cycles += 1;
                 }
+                let value = self.mem_read(address);
+                let result = self.modify_op(opcode, value);
+                self.mem_write(address, result);
+                address
+            }
+            Access::Other => return self.execute_other(opcode),
+        };
+        Ok((Some(operand_address), false))
+    }
 
-                /* STA */
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
-                    self.sta(&opcode.mode);
-                }
-                
-                0xAA => self.tax(),
-                0xe8 => self.inx(),
-                0x00 => return,
-                _ => todo!(),
+    /// Runs implied, accumulator, stack, jump and branch instructions.
+    /// Returns the same as `execute`.
+    fn execute_other(&mut self, opcode: &opcodes::OpCode) -> Result<(Option<u16>, bool), CpuError> {
+        if let Some(taken) = self.branch_condition(opcode.instruction) {
+            let (target, taken) = self.branch(&opcode.mode, taken)?;
+            return Ok((Some(target), taken));
+        }
+
+        match opcode.instruction {
+            /* Accumulator */
+            Instruction::ASL => {
+                let result = self.asl(self.register_a);
+                self.set_register_a(result);
+            }
+            Instruction::LSR => {
+                let result = self.lsr(self.register_a);
+                self.set_register_a(result);
+            }
+            Instruction::ROL => {
+                let result = self.rol(self.register_a);
+                self.set_register_a(result);
             }
+            Instruction::ROR => {
+                let result = self.ror(self.register_a);
+                self.set_register_a(result);
+            }
+            Instruction::INC => self.set_register_a(self.register_a.wrapping_add(1)),
+            Instruction::DEC => self.set_register_a(self.register_a.wrapping_sub(1)),
+
+            Instruction::INX => self.set_register_x(self.register_x.wrapping_add(1)),
+            Instruction::INY => self.set_register_y(self.register_y.wrapping_add(1)),
+            Instruction::DEX => self.set_register_x(self.register_x.wrapping_sub(1)),
+            Instruction::DEY => self.set_register_y(self.register_y.wrapping_sub(1)),
 
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.len - 1) as u16;
+            /* 65C02 JMP (Absolute,X) */
+            Instruction::JMP if opcode.mode == AddressingMode::NoneAddressing => {
+                let pointer = self
+                    .mem_read_u16(self.program_counter)
+                    .wrapping_add(self.register_x as u16);
+                self.program_counter = self.mem_read_u16(pointer);
+                return Ok((Some(self.program_counter), true));
             }
+            Instruction::JMP => return Ok((Some(self.jmp(&opcode.mode)?), true)),
+
+            /* Flags */
+            Instruction::CLC => self.set_carry_flag(false),
+            Instruction::SEC => self.set_carry_flag(true),
+            Instruction::CLI => self.status.remove(CpuFlags::INTERRUPT_DISABLE),
+            Instruction::SEI => self.status.insert(CpuFlags::INTERRUPT_DISABLE),
+            Instruction::CLD => self.status.remove(CpuFlags::DECIMAL),
+            Instruction::SED => self.status.insert(CpuFlags::DECIMAL),
+            Instruction::CLV => self.set_overflow_flag(false),
+
+            /* Transfers */
+            Instruction::TAX => self.tax(),
+            Instruction::TAY => self.tay(),
+            Instruction::TXA => self.txa(),
+            Instruction::TYA => self.tya(),
+            Instruction::TSX => self.tsx(),
+            Instruction::TXS => self.txs(),
+
+            /* Stack */
+            Instruction::PHA => self.pha(),
+            Instruction::PHX => self.stack_push(self.register_x),
+            Instruction::PHY => self.stack_push(self.register_y),
+            Instruction::PHP => self.php(),
+            Instruction::PLA => self.pla(),
+            Instruction::PLX => {
+                let data = self.stack_pop();
+                self.set_register_x(data);
+            }
+            Instruction::PLY => {
+                let data = self.stack_pop();
+                self.set_register_y(data);
+            }
+            Instruction::PLP => self.plp(),
+
+            /* Subroutines */
+            Instruction::JSR => return Ok((Some(self.jsr()), true)),
+            Instruction::RTS => self.rts(),
+
+            /* Interrupts */
+            Instruction::RTI => self.rti(),
+            Instruction::BRK => {
+                self.program_counter = self.program_counter.wrapping_add(1);
+                self.interrupt(interrupt::BRK);
+            }
+
+            Instruction::NOP => {}
+
+            /* JAM: the CPU locks up with PC stuck on the opcode */
+            Instruction::JAM => {
+                self.program_counter = self.program_counter.wrapping_sub(1);
+                return Err(CpuError::Jam {
+                    pc: self.program_counter,
+                    opcode: opcode.code,
+                });
+            }
+
+            /* BBRn, BBSn */
+            Instruction::BBR | Instruction::BBS => {
+                return Ok((None, self.branch_on_bit((opcode.code >> 4) & 0x07, opcode.code & 0x80 != 0)));
+            }
+
+            _ => {
+                return Err(CpuError::UnknownOpcode {
+                    pc: self.program_counter.wrapping_sub(1),
+                    opcode: opcode.code,
+                })
+            }
+        }
+        // these always load PC, even when it ends up where it started
+        Ok((None, matches!(opcode.instruction, Instruction::RTS | Instruction::RTI | Instruction::BRK)))
+    }
+
+    /// CLI, SEI and PLP change I after the interrupt poll, so their effect
+    /// on IRQs is delayed by one instruction. RTI restores it in time.
+    fn update_irq_inhibit(&mut self, code: u8, interrupt_disable: bool) {
+        self.irq_inhibit = match code {
+            0x58 | 0x78 | 0x28 => interrupt_disable,
+            _ => self.status.interrupt_disable(),
+        };
+    }
+
+    /// Executes exactly one instruction, servicing a pending interrupt first.
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        self.poll_bus_nmi();
+        let interrupt = self.pending_interrupt();
+        if let Some(interrupt) = interrupt {
+            self.interrupt(interrupt);
         }
+
+        let code = self.mem_read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        let opcode = &opcodes::opcode_table(self.variant)[code as usize];
+
+        let interrupt_disable = self.status.interrupt_disable();
+        let (operand_address, jumped) = self.execute(opcode)?;
+        self.update_irq_inhibit(code, interrupt_disable);
+
+        if !jumped {
+            self.program_counter = self.program_counter.wrapping_add((opcode.len - 1) as u16);
+        }
+
+        let interrupt_cycles = interrupt.map_or(0, |interrupt| interrupt.cpu_cycles);
+        let cycles = interrupt_cycles + opcode.cycles + self.extra_cycles;
+        self.extra_cycles = 0;
+        self.cycles += cycles as u64;
+
+        Ok(StepResult {
+            opcode,
+            operand_address,
+            cycles,
+            interrupt: interrupt.map(|interrupt| interrupt.itype),
+        })
     }
 }
@@ -0,0 +1,433 @@
+//! Cycle-stepped execution. Each `CPU::tick` makes exactly the bus access the
+//! NMOS 6502 makes on that cycle, including the dummy reads and writes that
+//! instruction-level stepping skips.
+//! http://nesdev.org/6502_cpu.txt
+
+use super::{
+    interrupt, page_cross, AddressingMode, CpuError, CpuFlags, CpuVariant, StepResult, CPU, STACK,
+};
+use crate::bus::Bus;
+use crate::opcodes::{self, Access, Instruction, OpCode};
+
+/// Progress through the instruction, or interrupt sequence, being ticked.
+#[derive(Default)]
+pub(super) struct CycleState {
+    /// Cycles done so far; 0 means the next tick fetches an opcode.
+    cycle: u8,
+    opcode: Option<&'static OpCode>,
+    /// The NMI or IRQ sequence being run.
+    interrupt: Option<interrupt::Interrupt>,
+    /// The NMI or IRQ sequence already run before `opcode`.
+    serviced: Option<interrupt::Interrupt>,
+    interrupt_disable: bool,
+    operand_address: Option<u16>,
+    address: u16,
+    base: u16,
+    page_crossed: bool,
+    pointer: u8,
+    data: u8,
+}
+
+impl<B: Bus> CPU<B> {
+    /// Runs one CPU cycle, making exactly one bus read or write. Returns the
+    /// instruction on the cycle that completes it; its `cycles` includes any
+    /// interrupt serviced before it, as with `step`.
+    ///
+    /// Don't call `step` while an instruction started by `tick` is unfinished.
+    ///
+    /// Only the NMOS access patterns are modelled, so on a 65C02 this returns
+    /// `CpuError::NoCycleStepping` without touching the bus.
+    pub fn tick(&mut self) -> Result<Option<StepResult>, CpuError> {
+        if self.variant == CpuVariant::Cmos65C02 {
+            return Err(CpuError::NoCycleStepping { variant: self.variant });
+        }
+
+        self.cycles += 1;
+        let cycle = self.cycle_state.cycle;
+        self.cycle_state.cycle += 1;
+
+        if cycle == 0 {
+            return self.tick_fetch();
+        }
+
+        let done = match self.cycle_state.opcode {
+            Some(opcode) if opcode.access == Access::Other => self.tick_other(opcode, cycle)?,
+            Some(opcode) => self.tick_memory(opcode, cycle),
+            None => {
+                if self.tick_interrupt(cycle) {
+                    self.cycle_state.cycle = 0;
+                    self.cycle_state.serviced = self.cycle_state.interrupt.take();
+                }
+                false
+            }
+        };
+
+        if !done {
+            return Ok(None);
+        }
+
+        let state = std::mem::take(&mut self.cycle_state);
+        let opcode = state.opcode.unwrap();
+        self.update_irq_inhibit(opcode.code, state.interrupt_disable);
+
+        let interrupt_cycles = state.serviced.map_or(0, |interrupt| interrupt.cpu_cycles);
+        Ok(Some(StepResult {
+            opcode,
+            operand_address: state.operand_address,
+            cycles: interrupt_cycles + state.cycle,
+            interrupt: state.serviced.map(|interrupt| interrupt.itype),
+        }))
+    }
+
+    /// Whether the next `tick` starts a new instruction.
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.cycle_state.cycle == 0 && self.cycle_state.serviced.is_none()
+    }
+
+    fn tick_fetch(&mut self) -> Result<Option<StepResult>, CpuError> {
+        // the first instruction of a handler runs before interrupts are polled again
+        if self.cycle_state.serviced.is_none() {
+            self.poll_bus_nmi();
+            if let Some(interrupt) = self.pending_interrupt() {
+                // the opcode is fetched but thrown away
+                self.mem_read(self.program_counter);
+                self.cycle_state.interrupt = Some(interrupt);
+                return Ok(None);
+            }
+        }
+
+        let pc = self.program_counter;
+        let code = self.mem_read(pc);
+        let opcode = &opcodes::opcode_table(self.variant)[code as usize];
+        if opcode.instruction == Instruction::JAM {
+            self.cycle_state = Default::default();
+            return Err(CpuError::Jam { pc, opcode: code });
+        }
+
+        self.program_counter = pc.wrapping_add(1);
+        self.cycle_state.opcode = Some(opcode);
+        self.cycle_state.interrupt_disable = self.status.interrupt_disable();
+        Ok(None)
+    }
+
+    fn fetch_operand(&mut self) -> u8 {
+        let data = self.mem_read(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+        data
+    }
+
+    fn set_effective_address(&mut self, address: u16) {
+        self.cycle_state.address = address;
+        self.cycle_state.operand_address = Some(address);
+    }
+
+    fn index_address(&mut self, base: u16, index: u8) {
+        let address = base.wrapping_add(index as u16);
+        self.cycle_state.base = base;
+        self.cycle_state.page_crossed = page_cross(base, address);
+        self.set_effective_address(address);
+    }
+
+    /// Cycles 2-7 of BRK, NMI and IRQ: push PC and P, then fetch the vector.
+    fn tick_interrupt(&mut self, cycle: u8) -> bool {
+        let interrupt = match self.cycle_state.opcode {
+            Some(opcode) if opcode.instruction == Instruction::BRK => interrupt::BRK,
+            _ => self.cycle_state.interrupt.unwrap_or(interrupt::BRK),
+        };
+        match cycle {
+            1 => {
+                self.mem_read(self.program_counter);
+                if interrupt.itype == interrupt::InterruptType::NMI {
+                    self.nmi_pending = false;
+                }
+            }
+            2 => self.stack_push((self.program_counter >> 8) as u8),
+            3 => self.stack_push(self.program_counter as u8),
+            4 => {
+                self.stack_push(self.status.to_pushed_byte(interrupt.break_flag));
+                self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+                self.irq_inhibit = true;
+            }
+            5 => {
+                // an NMI that arrived during the pushes hijacks the vector fetch
+                self.poll_bus_nmi();
+                let hijacked = interrupt.itype != interrupt::InterruptType::NMI && self.nmi_pending;
+                let vector_addr = if hijacked {
+                    self.nmi_pending = false;
+                    interrupt::NMI.vector_addr
+                } else {
+                    interrupt.vector_addr
+                };
+                self.cycle_state.address = vector_addr;
+                self.cycle_state.data = self.mem_read(vector_addr);
+            }
+            _ => {
+                let hi = self.mem_read(self.cycle_state.address.wrapping_add(1));
+                self.program_counter = (hi as u16) << 8 | self.cycle_state.data as u16;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Implied, stack, jump and branch instructions.
+    fn tick_other(&mut self, opcode: &'static OpCode, cycle: u8) -> Result<bool, CpuError> {
+        let pc = self.program_counter;
+        let stack_top = STACK + self.stack_pointer as u16;
+
+        match (opcode.instruction, cycle) {
+            (Instruction::BRK, 1) => {
+                self.tick_interrupt(cycle);
+                self.program_counter = pc.wrapping_add(1);
+            }
+            (Instruction::BRK, _) => return Ok(self.tick_interrupt(cycle)),
+
+            (Instruction::JSR, 1) => self.cycle_state.data = self.fetch_operand(),
+            (Instruction::JSR, 2) => {
+                self.mem_read(stack_top);
+            }
+            (Instruction::JSR, 3) => self.stack_push((pc >> 8) as u8),
+            (Instruction::JSR, 4) => self.stack_push(pc as u8),
+            (Instruction::JSR, _) => {
+                let hi = self.mem_read(pc);
+                let target = (hi as u16) << 8 | self.cycle_state.data as u16;
+                self.set_effective_address(target);
+                self.program_counter = target;
+                return Ok(true);
+            }
+
+            (Instruction::RTS, 1) | (Instruction::RTI, 1) | (Instruction::PHA, 1) | (Instruction::PHP, 1) | (Instruction::PLA, 1) | (Instruction::PLP, 1) => {
+                self.mem_read(pc);
+            }
+            (Instruction::RTS, 2) | (Instruction::RTI, 2) | (Instruction::PLA, 2) | (Instruction::PLP, 2) => {
+                self.mem_read(stack_top);
+            }
+            (Instruction::RTS, 3) => self.cycle_state.data = self.stack_pop(),
+            (Instruction::RTS, 4) => {
+                let hi = self.stack_pop();
+                self.program_counter = (hi as u16) << 8 | self.cycle_state.data as u16;
+            }
+            (Instruction::RTS, _) => {
+                self.fetch_operand();
+                return Ok(true);
+            }
+            (Instruction::RTI, 3) => {
+                let data = self.stack_pop();
+                self.status = CpuFlags::from_pushed_byte(data);
+            }
+            (Instruction::RTI, 4) => self.cycle_state.data = self.stack_pop(),
+            (Instruction::RTI, _) => {
+                let hi = self.stack_pop();
+                self.program_counter = (hi as u16) << 8 | self.cycle_state.data as u16;
+                return Ok(true);
+            }
+
+            (Instruction::PHA, _) => {
+                self.pha();
+                return Ok(true);
+            }
+            (Instruction::PHP, _) => {
+                self.php();
+                return Ok(true);
+            }
+            (Instruction::PLA, _) => {
+                self.pla();
+                return Ok(true);
+            }
+            (Instruction::PLP, _) => {
+                self.plp();
+                return Ok(true);
+            }
+
+            (Instruction::JMP, 1) => self.cycle_state.data = self.fetch_operand(),
+            (Instruction::JMP, 2) => {
+                let hi = self.fetch_operand();
+                let address = (hi as u16) << 8 | self.cycle_state.data as u16;
+                if opcode.mode == AddressingMode::Absolute {
+                    self.set_effective_address(address);
+                    self.program_counter = address;
+                    return Ok(true);
+                }
+                self.cycle_state.address = address;
+            }
+            (Instruction::JMP, 3) => self.cycle_state.data = self.mem_read(self.cycle_state.address),
+            (Instruction::JMP, _) => {
+                // the pointer's high byte is not carried into the next page
+                let pointer = self.cycle_state.address;
+                let hi = self.mem_read((pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff));
+                let target = (hi as u16) << 8 | self.cycle_state.data as u16;
+                self.set_effective_address(target);
+                self.program_counter = target;
+                return Ok(true);
+            }
+
+            (_, 1) if opcode.mode == AddressingMode::Relative => {
+                let offset = self.fetch_operand() as i8;
+                let target = self.program_counter.wrapping_add(offset as u16);
+                self.set_effective_address(target);
+                return Ok(!self.branch_condition(opcode.instruction).unwrap_or(false));
+            }
+            (_, 2) if opcode.mode == AddressingMode::Relative => {
+                self.mem_read(pc);
+                let target = self.cycle_state.address;
+                if !page_cross(pc, target) {
+                    self.program_counter = target;
+                    return Ok(true);
+                }
+                // PCL is fixed first; the high byte takes another cycle
+                self.program_counter = (pc & 0xff00) | (target & 0x00ff);
+            }
+            (_, _) if opcode.mode == AddressingMode::Relative => {
+                self.mem_read(pc);
+                self.program_counter = self.cycle_state.address;
+                return Ok(true);
+            }
+
+            /* two cycle implied and accumulator instructions read the next byte */
+            _ => {
+                self.mem_read(pc);
+                self.execute_other(opcode)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Instructions that read, write or modify memory: first resolve the
+    /// address, then do the data cycles.
+    fn tick_memory(&mut self, opcode: &'static OpCode, cycle: u8) -> bool {
+        let access = opcode.access;
+
+        match (opcode.mode, cycle) {
+            (AddressingMode::Immediate, _) => {
+                self.set_effective_address(self.program_counter);
+                let value = self.fetch_operand();
+                self.read_op(opcode, value);
+                return true;
+            }
+
+            (AddressingMode::ZeroPage, 1) => {
+                let address = self.fetch_operand() as u16;
+                self.set_effective_address(address);
+            }
+
+            (AddressingMode::ZeroPageX, 1) | (AddressingMode::ZeroPageY, 1) => {
+                self.cycle_state.pointer = self.fetch_operand();
+            }
+            (AddressingMode::ZeroPageX, 2) | (AddressingMode::ZeroPageY, 2) => {
+                let pointer = self.cycle_state.pointer;
+                self.mem_read(pointer as u16);
+                let index = if opcode.mode == AddressingMode::ZeroPageX {
+                    self.register_x
+                } else {
+                    self.register_y
+                };
+                self.set_effective_address(pointer.wrapping_add(index) as u16);
+            }
+
+            (AddressingMode::Absolute, 1)
+            | (AddressingMode::AbsoluteX, 1)
+            | (AddressingMode::AbsoluteY, 1) => {
+                self.cycle_state.data = self.fetch_operand();
+            }
+            (AddressingMode::Absolute, 2) => {
+                let hi = self.fetch_operand();
+                self.set_effective_address((hi as u16) << 8 | self.cycle_state.data as u16);
+            }
+            (AddressingMode::AbsoluteX, 2) | (AddressingMode::AbsoluteY, 2) => {
+                let hi = self.fetch_operand();
+                let base = (hi as u16) << 8 | self.cycle_state.data as u16;
+                let index = if opcode.mode == AddressingMode::AbsoluteX {
+                    self.register_x
+                } else {
+                    self.register_y
+                };
+                self.index_address(base, index);
+            }
+            (AddressingMode::AbsoluteX, 3) | (AddressingMode::AbsoluteY, 3) => {
+                return self.tick_unfixed_read(opcode, access);
+            }
+
+            (AddressingMode::IndirectX, 1) | (AddressingMode::IndirectY, 1) => {
+                self.cycle_state.pointer = self.fetch_operand();
+            }
+            (AddressingMode::IndirectX, 2) => {
+                let pointer = self.cycle_state.pointer;
+                self.mem_read(pointer as u16);
+                self.cycle_state.pointer = pointer.wrapping_add(self.register_x);
+            }
+            (AddressingMode::IndirectX, 3) | (AddressingMode::IndirectY, 2) => {
+                self.cycle_state.data = self.mem_read(self.cycle_state.pointer as u16);
+            }
+            (AddressingMode::IndirectX, 4) => {
+                let hi = self.mem_read(self.cycle_state.pointer.wrapping_add(1) as u16);
+                self.set_effective_address((hi as u16) << 8 | self.cycle_state.data as u16);
+            }
+            (AddressingMode::IndirectY, 3) => {
+                let hi = self.mem_read(self.cycle_state.pointer.wrapping_add(1) as u16);
+                let base = (hi as u16) << 8 | self.cycle_state.data as u16;
+                self.index_address(base, self.register_y);
+            }
+            (AddressingMode::IndirectY, 4) => {
+                return self.tick_unfixed_read(opcode, access);
+            }
+
+            (mode, _) => return self.tick_data(opcode, access, cycle - address_cycles(mode) - 1),
+        }
+        false
+    }
+
+    /// Indexed modes add the index to the low byte first and read from that
+    /// address before the carry reaches the high byte. A read that did not cross
+    /// a page is done; everything else treats it as a dummy read.
+    fn tick_unfixed_read(&mut self, opcode: &OpCode, access: Access) -> bool {
+        let address = self.cycle_state.address;
+        let unfixed = (self.cycle_state.base & 0xff00) | (address & 0x00ff);
+        let value = self.mem_read(unfixed);
+
+        if access == Access::Read && !self.cycle_state.page_crossed {
+            self.read_op(opcode, value);
+            return true;
+        }
+        false
+    }
+
+    fn tick_data(&mut self, opcode: &OpCode, access: Access, cycle: u8) -> bool {
+        let address = self.cycle_state.address;
+
+        match (access, cycle) {
+            (Access::Write, _) => {
+                let (address, value) = self.write_op(opcode, address, self.cycle_state.page_crossed);
+                self.mem_write(address, value);
+            }
+            (Access::ReadModifyWrite, 0) => {
+                self.cycle_state.data = self.mem_read(address);
+                return false;
+            }
+            /* the unmodified value is written back while the ALU works */
+            (Access::ReadModifyWrite, 1) => {
+                let value = self.cycle_state.data;
+                self.mem_write(address, value);
+                self.cycle_state.data = self.modify_op(opcode, value);
+                return false;
+            }
+            (Access::ReadModifyWrite, _) => self.mem_write(address, self.cycle_state.data),
+            _ => {
+                let value = self.mem_read(address);
+                self.read_op(opcode, value);
+            }
+        }
+        true
+    }
+}
+
+/// Cycles after the opcode fetch until the effective address is known.
+fn address_cycles(mode: AddressingMode) -> u8 {
+    match mode {
+        AddressingMode::ZeroPage => 1,
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY | AddressingMode::Absolute => 2,
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 3,
+        AddressingMode::IndirectX | AddressingMode::IndirectY => 4,
+        _ => 0,
+    }
+}
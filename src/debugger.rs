@@ -0,0 +1,415 @@
+//! An interactive debugger built on `CPU::step` and the disassembler.
+//!
+//! `Debugger::command` takes one line of input and returns the text to show,
+//! so the same commands drive the REPL in `main.rs` and the tests.
+
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::bus::{Bus, FlatRam};
+use crate::cpu::{CpuFlags, CpuVariant, StepResult, CPU};
+use crate::disasm::{self, Symbols};
+use crate::opcodes::{self, Instruction};
+use crate::trace::{self, TraceFormat};
+
+/// How many instructions `continue`, `next` and `finish` run before giving
+/// up, so a program stuck in a loop hands control back.
+pub const DEFAULT_RUN_LIMIT: u64 = 10_000_000;
+
+const HELP: &str = "\
+break [ADDR]          set a breakpoint, or list them (b)
+delete ADDR           remove a breakpoint
+watch ADDR [r|w|rw]   stop when ADDR is read and/or written (default rw)
+unwatch ADDR          remove a watchpoint
+step [N]              run N instructions (s)
+next                  step, running a JSR through to its return (n)
+finish                run until the current subroutine returns
+continue [N]          run until a breakpoint, watchpoint or trap (c)
+regs [REG VALUE]      show registers, or set A X Y SP PC or P (r)
+mem ADDR [LEN]        dump memory (m)
+write ADDR BYTE...    write memory (w)
+dis [ADDR] [N]        disassemble around PC, or from ADDR (d)
+quit                  leave the debugger (q)
+Numbers, counts and lengths included, are hex, with or without a $ or 0x
+prefix. Labels work too.";
+
+/// A watched access that happened during the last instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    pub data: u8,
+    pub write: bool,
+}
+
+/// Wraps a bus and records accesses to watched addresses.
+pub struct WatchBus<B: Bus> {
+    pub inner: B,
+    reads: BTreeSet<u16>,
+    writes: BTreeSet<u16>,
+    hits: Vec<WatchHit>,
+}
+
+impl<B: Bus> WatchBus<B> {
+    pub fn new(inner: B) -> Self {
+        WatchBus {
+            inner,
+            reads: BTreeSet::new(),
+            writes: BTreeSet::new(),
+            hits: Vec::new(),
+        }
+    }
+}
+
+impl<B: Bus> Bus for WatchBus<B> {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.inner.read(address);
+        if self.reads.contains(&address) {
+            self.hits.push(WatchHit { address, data, write: false });
+        }
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if self.writes.contains(&address) {
+            self.hits.push(WatchHit { address, data, write: true });
+        }
+        self.inner.write(address, data);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.inner.peek(address)
+    }
+
+    fn take_nmi(&mut self) -> bool {
+        self.inner.take_nmi()
+    }
+}
+
+/// What the REPL should do with a command's result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Text(String),
+    Quit,
+}
+
+pub struct Debugger<B: Bus = FlatRam> {
+    pub cpu: CPU<WatchBus<B>>,
+    /// Labels used in disassembly and accepted in place of addresses.
+    pub symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+}
+
+impl Debugger<FlatRam> {
+    pub fn new(variant: CpuVariant) -> Self {
+        Debugger::with_bus(variant, FlatRam::new())
+    }
+}
+
+impl<B: Bus> Debugger<B> {
+    pub fn with_bus(variant: CpuVariant, bus: B) -> Self {
+        Debugger {
+            cpu: CPU::with_bus(variant, WatchBus::new(bus)),
+            symbols: Symbols::new(),
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Runs one line of input.
+    pub fn command(&mut self, line: &str) -> Reply {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Reply::Text(String::new()),
+        };
+        let result = match name {
+            "b" | "break" => self.break_command(args),
+            "delete" => self.delete(args),
+            "watch" => self.watch(args),
+            "unwatch" => self.unwatch(args),
+            "s" | "step" => self.step(args),
+            "n" | "next" => Ok(self.next()),
+            "finish" => Ok(self.finish()),
+            "c" | "continue" => self.continue_command(args),
+            "r" | "regs" => self.regs(args),
+            "m" | "mem" => self.mem(args),
+            "w" | "write" => self.write(args),
+            "d" | "dis" => self.dis(args),
+            "h" | "help" => Ok(HELP.to_string()),
+            "q" | "quit" => return Reply::Quit,
+            _ => Err(format!("unknown command `{}`, try `help`", name)),
+        };
+        Reply::Text(result.unwrap_or_else(|message| format!("error: {}", message)))
+    }
+
+    /// The next instruction and the registers, in Mesen's trace layout.
+    pub fn location(&self) -> String {
+        trace::format_line(&self.cpu, TraceFormat::Mesen)
+    }
+
+    fn number(&self, text: &str) -> Result<u16, String> {
+        if let Some((&address, _)) = self.symbols.iter().find(|(_, name)| name.as_str() == text) {
+            return Ok(address);
+        }
+        let digits = text
+            .strip_prefix('$')
+            .or_else(|| text.strip_prefix("0x"))
+            .unwrap_or(text);
+        u16::from_str_radix(digits, 16).map_err(|_| format!("`{}` is not a hex number or label", text))
+    }
+
+    fn address_arg(&self, args: &[&str]) -> Result<u16, String> {
+        match args.first() {
+            Some(text) => self.number(text),
+            None => Err("expected an address".to_string()),
+        }
+    }
+
+    fn break_command(&mut self, args: &[&str]) -> Result<String, String> {
+        if args.is_empty() {
+            let list: Vec<String> = self.breakpoints.iter().map(|address| format!("${:04X}", address)).collect();
+            return Ok(if list.is_empty() {
+                "no breakpoints".to_string()
+            } else {
+                list.join(" ")
+            });
+        }
+        let address = self.address_arg(args)?;
+        self.breakpoints.insert(address);
+        Ok(format!("breakpoint at ${:04X}", address))
+    }
+
+    fn delete(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = self.address_arg(args)?;
+        if self.breakpoints.remove(&address) {
+            Ok(format!("deleted breakpoint at ${:04X}", address))
+        } else {
+            Err(format!("no breakpoint at ${:04X}", address))
+        }
+    }
+
+    fn watch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = self.address_arg(args)?;
+        let (read, write) = match args.get(1).copied() {
+            None | Some("rw") => (true, true),
+            Some("r") => (true, false),
+            Some("w") => (false, true),
+            Some(other) => return Err(format!("expected r, w or rw, not `{}`", other)),
+        };
+        let bus = &mut self.cpu.bus;
+        if read {
+            bus.reads.insert(address);
+        }
+        if write {
+            bus.writes.insert(address);
+        }
+        Ok(format!("watching ${:04X}", address))
+    }
+
+    fn unwatch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = self.address_arg(args)?;
+        let bus = &mut self.cpu.bus;
+        if bus.reads.remove(&address) | bus.writes.remove(&address) {
+            Ok(format!("stopped watching ${:04X}", address))
+        } else {
+            Err(format!("${:04X} is not watched", address))
+        }
+    }
+
+    /// Steps until `done` is true, or a breakpoint, watchpoint, error or
+    /// self-loop stops it first. Returns why it stopped and where.
+    fn run<F>(&mut self, limit: u64, mut done: F) -> String
+    where
+        F: FnMut(&CPU<WatchBus<B>>, &StepResult) -> bool,
+    {
+        for _ in 0..limit {
+            let pc = self.cpu.program_counter;
+            self.cpu.bus.hits.clear();
+            let step = match self.cpu.step() {
+                Ok(step) => step,
+                Err(err) => return format!("{}\n{}", err, self.location()),
+            };
+
+            let mut reason = String::new();
+            for hit in self.cpu.bus.hits.drain(..) {
+                let (access, arrow) = if hit.write { ("write", "<-") } else { ("read", "->") };
+                let _ = writeln!(reason, "watchpoint: {} ${:04X} {} ${:02X}", access, hit.address, arrow, hit.data);
+            }
+            if !reason.is_empty() || done(&self.cpu, &step) {
+                return reason + &self.location();
+            }
+            if self.breakpoints.contains(&self.cpu.program_counter) {
+                return format!("breakpoint at ${:04X}\n{}", self.cpu.program_counter, self.location());
+            }
+            if self.cpu.program_counter == pc && step.interrupt.is_none() {
+                return format!("trapped in a loop at ${:04X}\n{}", pc, self.location());
+            }
+        }
+        format!("stopped after {} instructions\n{}", limit, self.location())
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(text) => self.number(text)? as u64,
+            None => 1,
+        };
+        let mut steps = 0;
+        Ok(self.run(count, |_, _| {
+            steps += 1;
+            steps == count
+        }))
+    }
+
+    fn next(&mut self) -> String {
+        let code = self.cpu.read_memory(self.cpu.program_counter);
+        let opcode = &opcodes::opcode_table(self.cpu.variant)[code as usize];
+        if opcode.instruction != Instruction::JSR {
+            return self.run(1, |_, _| true);
+        }
+        let return_address = self.cpu.program_counter.wrapping_add(3);
+        let stack_pointer = self.cpu.stack_pointer;
+        self.run(DEFAULT_RUN_LIMIT, |cpu, _| {
+            cpu.program_counter == return_address && cpu.stack_pointer == stack_pointer
+        })
+    }
+
+    fn finish(&mut self) -> String {
+        let stack_pointer = self.cpu.stack_pointer;
+        self.run(DEFAULT_RUN_LIMIT, |cpu, step| {
+            matches!(step.opcode.instruction, Instruction::RTS | Instruction::RTI)
+                && cpu.stack_pointer > stack_pointer
+        })
+    }
+
+    fn continue_command(&mut self, args: &[&str]) -> Result<String, String> {
+        let limit = match args.first() {
+            Some(text) => self.number(text)? as u64,
+            None => DEFAULT_RUN_LIMIT,
+        };
+        Ok(self.run(limit, |_, _| false))
+    }
+
+    fn regs(&mut self, args: &[&str]) -> Result<String, String> {
+        if let [register, value] = args {
+            let value = self.number(value)?;
+            let byte = u8::try_from(value).map_err(|_| format!("${:X} does not fit in a register", value));
+            let cpu = &mut self.cpu;
+            match register.to_ascii_lowercase().as_str() {
+                "a" => cpu.register_a = byte?,
+                "x" => cpu.register_x = byte?,
+                "y" => cpu.register_y = byte?,
+                "sp" => cpu.stack_pointer = byte?,
+                "p" => cpu.status = CpuFlags::from_pushed_byte(byte?),
+                "pc" => cpu.program_counter = value,
+                _ => return Err(format!("unknown register `{}`", register)),
+            }
+        } else if !args.is_empty() {
+            return Err("usage: regs [REG VALUE]".to_string());
+        }
+        let cpu = &self.cpu;
+        Ok(format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} P:{:02X} CYC:{}",
+            cpu.program_counter,
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.stack_pointer,
+            cpu.status.bits(),
+            cpu.cycles
+        ))
+    }
+
+    fn mem(&self, args: &[&str]) -> Result<String, String> {
+        let start = self.address_arg(args)?;
+        let len = match args.get(1) {
+            Some(text) => self.number(text)? as u32,
+            None => 0x40,
+        };
+        let mut out = String::new();
+        for row in (0..len).step_by(16) {
+            let address = start.wrapping_add(row as u16);
+            let bytes: Vec<u8> = (0..16.min(len - row) as u16)
+                .map(|i| self.cpu.read_memory(address.wrapping_add(i)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            let _ = writeln!(out, "{:04X}: {:47}  {}", address, hex.join(" "), text);
+        }
+        Ok(out.trim_end().to_string())
+    }
+
+    fn write(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = self.address_arg(args)?;
+        if args.len() < 2 {
+            return Err("expected bytes to write".to_string());
+        }
+        for (i, text) in args[1..].iter().enumerate() {
+            let byte = self.number(text)?;
+            let byte = u8::try_from(byte).map_err(|_| format!("${:X} is not a byte", byte))?;
+            // straight to the wrapped bus, so writing doesn't trip a watchpoint
+            self.cpu.bus.inner.write(address.wrapping_add(i as u16), byte);
+        }
+        Ok(format!("wrote {} bytes at ${:04X}", args.len() - 1, address))
+    }
+
+    /// Finds a start address up to `CONTEXT` instructions before `pc` that
+    /// decodes into an instruction boundary at `pc`, so the listing shows
+    /// what led up to it.
+    fn start_before(&self, pc: u16) -> u16 {
+        const CONTEXT: usize = 3;
+        for back in (1..=3 * CONTEXT as u16).rev() {
+            let start = pc.wrapping_sub(back);
+            let mut address = start;
+            let mut count = 0;
+            while address != pc && pc.wrapping_sub(address) <= back {
+                address = self.decode(address).next_address();
+                count += 1;
+            }
+            if address == pc && count <= CONTEXT {
+                return start;
+            }
+        }
+        pc
+    }
+
+    fn decode(&self, address: u16) -> disasm::Instruction {
+        let bytes: Vec<u8> = (0..3).map(|i| self.cpu.read_memory(address.wrapping_add(i))).collect();
+        disasm::decode(self.cpu.variant, &bytes, address)
+    }
+
+    fn dis(&self, args: &[&str]) -> Result<String, String> {
+        let pc = self.cpu.program_counter;
+        let start = match args.first() {
+            Some(text) => self.number(text)?,
+            None => self.start_before(pc),
+        };
+        let count = match args.get(1) {
+            Some(text) => self.number(text)? as usize,
+            None => 10,
+        };
+
+        let mut out = String::new();
+        let mut address = start;
+        for _ in 0..count {
+            let instruction = self.decode(address);
+            let marker = if address == pc { '>' } else { ' ' };
+            if let Some(label) = self.symbols.get(&address) {
+                let _ = writeln!(out, "        {}:", label);
+            }
+            let hex: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let _ = writeln!(
+                out,
+                "{} {:04X}  {:8}  {}",
+                marker,
+                address,
+                hex.join(" "),
+                instruction.with_symbols(&self.symbols)
+            );
+            address = instruction.next_address();
+        }
+        Ok(out.trim_end().to_string())
+    }
+}
@@ -0,0 +1,217 @@
+//! Turns machine code back into assembly, using the same opcode tables as the CPU.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::bus::Bus;
+use crate::cpu::{AddressingMode, CpuVariant};
+use crate::opcodes::{self, OpCode};
+
+/// Labels to show in place of addresses.
+pub type Symbols = HashMap<u16, String>;
+
+/// One decoded instruction, or a data byte that does not start one.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// `None` for a byte shown as `.db`: a JAM opcode, or an instruction cut
+    /// short by the end of the input.
+    pub opcode: Option<&'static OpCode>,
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The address after this instruction.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.len() as u16)
+    }
+
+    fn byte(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    fn word(&self) -> u16 {
+        (self.bytes[2] as u16) << 8 | self.bytes[1] as u16
+    }
+
+    fn branch_target(&self, offset: u8) -> u16 {
+        self.next_address().wrapping_add(offset as i8 as u16)
+    }
+
+    /// The address written in the operand: a zero page or absolute address,
+    /// a branch target, or the pointer of an indirect mode. `None` for
+    /// implied, accumulator and immediate operands.
+    pub fn operand_address(&self) -> Option<u16> {
+        let opcode = self.opcode?;
+        match opcode.mode {
+            AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageX
+            | AddressingMode::ZeroPageY
+            | AddressingMode::IndirectX
+            | AddressingMode::IndirectY
+            | AddressingMode::ZeroPageIndirect => Some(self.byte() as u16),
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteX
+            | AddressingMode::AbsoluteY
+            | AddressingMode::Indirect => Some(self.word()),
+            AddressingMode::Relative => Some(self.branch_target(self.byte())),
+            AddressingMode::NoneAddressing if opcode.len == 3 => match opcode.instruction {
+                opcodes::Instruction::BBR | opcodes::Instruction::BBS => Some(self.branch_target(self.bytes[2])),
+                _ => Some(self.word()),
+            },
+            _ => None,
+        }
+    }
+
+    /// Shows the instruction with labels from `symbols` in place of addresses.
+    pub fn with_symbols<'a>(&'a self, symbols: &'a Symbols) -> WithSymbols<'a> {
+        WithSymbols {
+            instruction: self,
+            symbols,
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, symbols: Option<&Symbols>) -> fmt::Result {
+        let opcode = match self.opcode {
+            Some(opcode) => opcode,
+            None => return write!(f, ".db ${:02X}", self.bytes[0]),
+        };
+
+        let address = |f: &mut fmt::Formatter, address: u16, zero_page: bool| -> fmt::Result {
+            match symbols.and_then(|symbols| symbols.get(&address)) {
+                Some(label) => write!(f, "{}", label),
+                None if zero_page => write!(f, "${:02X}", address),
+                None => write!(f, "${:04X}", address),
+            }
+        };
+
+        write!(f, "{}", opcode.mnemonic)?;
+        let operand = match self.operand_address() {
+            Some(operand) => operand,
+            None => {
+                return match opcode.mode {
+                    AddressingMode::Immediate => write!(f, " #${:02X}", self.byte()),
+                    AddressingMode::Accumulator => write!(f, " A"),
+                    _ => Ok(()),
+                }
+            }
+        };
+
+        f.write_str(" ")?;
+        match opcode.mode {
+            AddressingMode::ZeroPage => address(f, operand, true),
+            AddressingMode::ZeroPageX => address(f, operand, true).and_then(|_| f.write_str(",X")),
+            AddressingMode::ZeroPageY => address(f, operand, true).and_then(|_| f.write_str(",Y")),
+            AddressingMode::AbsoluteX => address(f, operand, false).and_then(|_| f.write_str(",X")),
+            AddressingMode::AbsoluteY => address(f, operand, false).and_then(|_| f.write_str(",Y")),
+            AddressingMode::IndirectX => {
+                f.write_str("(")?;
+                address(f, operand, true)?;
+                f.write_str(",X)")
+            }
+            AddressingMode::IndirectY => {
+                f.write_str("(")?;
+                address(f, operand, true)?;
+                f.write_str("),Y")
+            }
+            AddressingMode::ZeroPageIndirect => {
+                f.write_str("(")?;
+                address(f, operand, true)?;
+                f.write_str(")")
+            }
+            AddressingMode::Indirect => {
+                f.write_str("(")?;
+                address(f, operand, false)?;
+                f.write_str(")")
+            }
+            AddressingMode::NoneAddressing => match opcode.instruction {
+                // 65C02 BBRn/BBSn zp,target
+                opcodes::Instruction::BBR | opcodes::Instruction::BBS => {
+                    address(f, self.byte() as u16, true)?;
+                    f.write_str(",")?;
+                    address(f, operand, false)
+                }
+                // 65C02 JMP ($nnnn,X)
+                opcodes::Instruction::JMP => {
+                    f.write_str("(")?;
+                    address(f, operand, false)?;
+                    f.write_str(",X)")
+                }
+                _ => address(f, operand, false),
+            },
+            _ => address(f, operand, false),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+/// An instruction displayed with labels, from `Instruction::with_symbols`.
+pub struct WithSymbols<'a> {
+    instruction: &'a Instruction,
+    symbols: &'a Symbols,
+}
+
+impl fmt::Display for WithSymbols<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.instruction.write(f, Some(self.symbols))
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, which sits at `address`.
+/// `bytes` must not be empty.
+pub fn decode(variant: CpuVariant, bytes: &[u8], address: u16) -> Instruction {
+    let opcode = &opcodes::opcode_table(variant)[bytes[0] as usize];
+    let len = opcode.len as usize;
+    if opcode.instruction == opcodes::Instruction::JAM || bytes.len() < len {
+        return Instruction {
+            address,
+            bytes: vec![bytes[0]],
+            opcode: None,
+        };
+    }
+    Instruction {
+        address,
+        bytes: bytes[..len].to_vec(),
+        opcode: Some(opcode),
+    }
+}
+
+/// Decodes all of `bytes`, loaded at `origin`.
+pub fn disassemble(variant: CpuVariant, bytes: &[u8], origin: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let instruction = decode(variant, &bytes[offset..], origin.wrapping_add(offset as u16));
+        offset += instruction.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Decodes the instructions starting in `range`, reading memory with `peek`.
+/// The last one may extend past the end of the range.
+pub fn disassemble_bus<B: Bus>(variant: CpuVariant, bus: &B, range: RangeInclusive<u16>) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = *range.start() as u32;
+    while address <= *range.end() as u32 {
+        let bytes: Vec<u8> = (0..3).map(|i| bus.peek((address as u16).wrapping_add(i))).collect();
+        let instruction = decode(variant, &bytes, address as u16);
+        address += instruction.len() as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
@@ -0,0 +1,339 @@
+//! A GDB Remote Serial Protocol stub, so tools that speak GDB-RSP can drive
+//! the CPU over TCP.
+//!
+//! Registers, in the order `g` and `G` use and numbered that way for `p` and
+//! `P`: A, X, Y, P and SP as one byte each, then PC as two bytes, low first.
+//! Software (`Z0`) and hardware (`Z1`) breakpoints are both kept as a list of
+//! PCs to stop at, so memory is never patched.
+
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::ops::Range;
+
+use crate::bus::Bus;
+use crate::cpu::{CpuFlags, CPU};
+
+/// The largest packet we accept, as advertised in `qSupported`. Memory is
+/// sent as hex, so `m` and `M` move at most half as many bytes.
+const PACKET_SIZE: usize = 0x4000;
+
+/// Instructions run between checks for an interrupt from the client.
+const POLL_INTERVAL: usize = 10_000;
+
+/// Stop replies: SIGINT, SIGILL and SIGTRAP.
+const STOP_INTERRUPT: &str = "S02";
+const STOP_ILLEGAL: &str = "S04";
+const STOP_TRAP: &str = "S05";
+
+/// What to do after a packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Reply(String),
+    /// Run until a breakpoint or an interrupt, then send a stop reply.
+    Resume,
+    /// Send the reply, if any, and close the connection.
+    Close(Option<String>),
+}
+
+pub struct GdbStub<B: Bus> {
+    pub cpu: CPU<B>,
+    breakpoints: BTreeSet<u16>,
+    no_ack: bool,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// Splits `addr,len` into a start address and length, refusing lengths
+/// whose hex would not fit in a packet.
+fn address_and_length(text: &str) -> Option<(u16, usize)> {
+    let (address, length) = text.split_once(',')?;
+    let address = parse_number(address)?;
+    let length = parse_number(length)? as usize;
+    if address > 0xffff || length > PACKET_SIZE / 2 {
+        return None;
+    }
+    Some((address as u16, length))
+}
+
+/// Where register `number` sits in the `g` packet.
+fn register_range(number: u32) -> Option<Range<usize>> {
+    match number {
+        0..=4 => Some(number as usize..number as usize + 1),
+        5 => Some(5..7),
+        _ => None,
+    }
+}
+
+/// Reads one byte, or `None` once the client has disconnected.
+fn read_byte(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+impl<B: Bus> GdbStub<B> {
+    pub fn new(cpu: CPU<B>) -> Self {
+        GdbStub {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            no_ack: false,
+        }
+    }
+
+    fn registers(&self) -> [u8; 7] {
+        let cpu = &self.cpu;
+        let pc = cpu.program_counter;
+        [
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.status.bits(),
+            cpu.stack_pointer,
+            pc as u8,
+            (pc >> 8) as u8,
+        ]
+    }
+
+    fn set_register(&mut self, number: u32, value: &[u8]) -> bool {
+        let cpu = &mut self.cpu;
+        match (number, value) {
+            (0, [a]) => cpu.register_a = *a,
+            (1, [x]) => cpu.register_x = *x,
+            (2, [y]) => cpu.register_y = *y,
+            (3, [p]) => cpu.status = CpuFlags::from_pushed_byte(*p),
+            (4, [sp]) => cpu.stack_pointer = *sp,
+            (5, [lo, hi]) => cpu.program_counter = (*hi as u16) << 8 | *lo as u16,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Runs one instruction. Returns a stop reply if it should stop here.
+    fn step(&mut self) -> Option<&'static str> {
+        if self.cpu.step().is_err() {
+            return Some(STOP_ILLEGAL);
+        }
+        if self.breakpoints.contains(&self.cpu.program_counter) {
+            return Some(STOP_TRAP);
+        }
+        None
+    }
+
+    /// Handles the contents of one packet, without the `$` and checksum.
+    pub fn handle_packet(&mut self, packet: &str) -> Response {
+        let reply = |text: &str| Response::Reply(text.to_string());
+        let error = reply("E01");
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        match command {
+            "?" => reply(STOP_TRAP),
+            "g" => Response::Reply(to_hex(&self.registers())),
+            "G" => match from_hex(args) {
+                Some(bytes) if bytes.len() == 7 => {
+                    for number in 0..6 {
+                        self.set_register(number, &bytes[register_range(number).unwrap()]);
+                    }
+                    reply("OK")
+                }
+                _ => error,
+            },
+            "p" => match parse_number(args).and_then(register_range) {
+                Some(range) => Response::Reply(to_hex(&self.registers()[range])),
+                None => error,
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(number, value)| {
+                    Some(self.set_register(parse_number(number)?, &from_hex(value)?))
+                });
+                if written == Some(true) {
+                    reply("OK")
+                } else {
+                    error
+                }
+            }
+            "m" => match address_and_length(args) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0..length)
+                        .map(|i| self.cpu.read_memory(address.wrapping_add(i as u16)))
+                        .collect();
+                    Response::Reply(to_hex(&bytes))
+                }
+                None => error,
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = address_and_length(range)?;
+                    let bytes = from_hex(data)?;
+                    if bytes.len() == length {
+                        Some((address, bytes))
+                    } else {
+                        None
+                    }
+                });
+                match parsed {
+                    Some((address, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            self.cpu.bus.write(address.wrapping_add(i as u16), byte);
+                        }
+                        reply("OK")
+                    }
+                    None => error,
+                }
+            }
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(parse_number);
+                match (kind, address) {
+                    (Some("0") | Some("1"), Some(address @ 0..=0xffff)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address as u16);
+                        } else {
+                            self.breakpoints.remove(&(address as u16));
+                        }
+                        reply("OK")
+                    }
+                    // watchpoints are not supported
+                    (Some(_), Some(_)) => reply(""),
+                    _ => error,
+                }
+            }
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_number(args) {
+                        Some(address @ 0..=0xffff) => self.cpu.program_counter = address as u16,
+                        _ => return error,
+                    }
+                }
+                if command == "s" {
+                    Response::Reply(self.step().unwrap_or(STOP_TRAP).to_string())
+                } else {
+                    Response::Resume
+                }
+            }
+            "H" => reply("OK"),
+            "D" => Response::Close(Some("OK".to_string())),
+            "k" => Response::Close(None),
+            "q" if args.starts_with("Supported") => Response::Reply(format!("PacketSize={:x};QStartNoAckMode+", PACKET_SIZE)),
+            "q" if args == "Attached" => reply("1"),
+            "Q" if args == "StartNoAckMode" => {
+                self.no_ack = true;
+                reply("OK")
+            }
+            // anything else is unsupported, which an empty reply says
+            _ => reply(""),
+        }
+    }
+
+    /// Runs until a breakpoint, an error or a Ctrl-C (0x03) from the client.
+    fn resume(&mut self, stream: &mut TcpStream) -> io::Result<String> {
+        stream.set_nonblocking(true)?;
+        let stop = loop {
+            if let Some(stop) = (0..POLL_INTERVAL).find_map(|_| self.step()) {
+                break stop;
+            }
+            let mut byte = [0];
+            match stream.read(&mut byte) {
+                Ok(0) => break STOP_INTERRUPT,
+                Ok(_) if byte[0] == 0x03 => break STOP_INTERRUPT,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        };
+        stream.set_nonblocking(false)?;
+        Ok(stop.to_string())
+    }
+
+    fn send(&self, stream: &mut TcpStream, data: &str) -> io::Result<()> {
+        write!(stream, "${}#{:02x}", data, checksum(data))?;
+        stream.flush()
+    }
+
+    /// Reads the next packet, acknowledging it. Returns `None` when the client
+    /// disconnects. A lone 0x03 reads as a `?` query.
+    fn receive(&self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(0x03) => return Ok(Some("?".to_string())),
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match read_byte(stream)? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut sum = [0; 2];
+            stream.read_exact(&mut sum)?;
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+
+            if !self.no_ack {
+                stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    /// Serves one client until it detaches, kills the session or disconnects.
+    pub fn serve(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        while let Some(packet) = self.receive(&mut stream)? {
+            match self.handle_packet(&packet) {
+                Response::Reply(reply) => self.send(&mut stream, &reply)?,
+                Response::Resume => {
+                    let stop = self.resume(&mut stream)?;
+                    self.send(&mut stream, &stop)?;
+                }
+                Response::Close(reply) => {
+                    if let Some(reply) = reply {
+                        self.send(&mut stream, &reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for one debugger to connect on `address`, then serves it.
+    pub fn listen<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+}
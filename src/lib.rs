@@ -1,5 +1,14 @@
+pub mod asm;
+pub mod bus;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+pub mod loader;
 pub mod opcodes;
+pub mod sandbox;
+pub mod snake;
+pub mod trace;
 
 #[macro_use]
-extern crate lazy_static;
\ No newline at end of file
+extern crate bitflags;
\ No newline at end of file
@@ -0,0 +1,199 @@
+//! Program images made of segments, read from raw binaries, Intel HEX or
+//! Motorola S-record files. `CPU::load_image` copies one into memory.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// A segment would run past $FFFF.
+    DoesNotFit { origin: u32, len: usize },
+    /// A malformed record in a HEX or S-record file. Lines count from 1.
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::DoesNotFit { origin, len } => {
+                write!(f, "{} bytes at ${:04X} do not fit below $10000", len, origin)
+            }
+            LoadError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Bytes to be copied to consecutive addresses starting at `origin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Segment {
+    /// Fails if the bytes would run past $FFFF.
+    pub fn new(origin: u32, bytes: Vec<u8>) -> Result<Self, LoadError> {
+        if origin as usize + bytes.len() > 0x10000 {
+            return Err(LoadError::DoesNotFit { origin, len: bytes.len() });
+        }
+        Ok(Segment { origin: origin as u16, bytes })
+    }
+
+    pub fn end(&self) -> usize {
+        self.origin as usize + self.bytes.len()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    /// The start address, if the file gives one.
+    pub start: Option<u16>,
+}
+
+fn syntax(line: usize, message: &str) -> LoadError {
+    LoadError::Syntax {
+        line,
+        message: message.to_string(),
+    }
+}
+
+/// Decodes the hex digits of a record into bytes.
+fn record_bytes(line: usize, digits: &str) -> Result<Vec<u8>, LoadError> {
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(syntax(line, "expected pairs of hex digits"));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn start_address(line: usize, address: u32) -> Result<u16, LoadError> {
+    if address > 0xffff {
+        return Err(syntax(line, &format!("start address ${:X} is past $FFFF", address)));
+    }
+    Ok(address as u16)
+}
+
+fn big_endian(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |value, &byte| value << 8 | byte as u32)
+}
+
+impl Image {
+    /// A raw binary loaded at `origin`.
+    pub fn raw(origin: u16, bytes: Vec<u8>) -> Result<Self, LoadError> {
+        Ok(Image {
+            segments: vec![Segment::new(origin as u32, bytes)?],
+            start: None,
+        })
+    }
+
+    /// The byte the image puts at `address`, if any.
+    pub fn byte_at(&self, address: u16) -> Option<u8> {
+        self.segments.iter().find_map(|segment| {
+            let offset = address.checked_sub(segment.origin)?;
+            segment.bytes.get(offset as usize).copied()
+        })
+    }
+
+    /// Where execution should begin: the file's start address, else the reset
+    /// vector if the image sets it, else the first segment.
+    pub fn entry(&self) -> Option<u16> {
+        let reset = match (self.byte_at(0xfffc), self.byte_at(0xfffd)) {
+            (Some(lo), Some(hi)) => Some(u16::from_le_bytes([lo, hi])),
+            _ => None,
+        };
+        self.start
+            .or(reset)
+            .or_else(|| self.segments.first().map(|segment| segment.origin))
+    }
+
+    /// Adds bytes at `address`, extending the last segment if they follow on.
+    fn push(&mut self, address: u32, data: &[u8]) -> Result<(), LoadError> {
+        let segment = Segment::new(address, data.to_vec())?;
+        match self.segments.last_mut() {
+            Some(last) if last.end() == address as usize => last.bytes.extend_from_slice(data),
+            _ => self.segments.push(segment),
+        }
+        Ok(())
+    }
+
+    /// Parses Intel HEX: data, end of file, extended segment and linear
+    /// address, and start address records.
+    pub fn from_intel_hex(text: &str) -> Result<Self, LoadError> {
+        let mut image = Image::default();
+        let mut base = 0u32;
+        for (index, record) in text.lines().enumerate() {
+            let line = index + 1;
+            let record = record.trim();
+            if record.is_empty() {
+                continue;
+            }
+            let digits = record.strip_prefix(':').ok_or_else(|| syntax(line, "expected `:`"))?;
+            let bytes = record_bytes(line, digits)?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(syntax(line, "record length does not match its byte count"));
+            }
+            if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+                return Err(syntax(line, "bad checksum"));
+            }
+            let address = big_endian(&bytes[1..3]);
+            let data = &bytes[4..bytes.len() - 1];
+            match (bytes[3], data.len()) {
+                (0x00, _) => image.push(base + address, data)?,
+                (0x01, _) => break,
+                (0x02, 2) => base = big_endian(data) << 4,
+                (0x04, 2) => base = big_endian(data) << 16,
+                (0x03, 4) => {
+                    let (cs, ip) = (big_endian(&data[..2]), big_endian(&data[2..]));
+                    image.start = Some(start_address(line, (cs << 4) + ip)?);
+                }
+                (0x05, 4) => image.start = Some(start_address(line, big_endian(data))?),
+                (0x02..=0x05, _) => return Err(syntax(line, "wrong length for an address record")),
+                (kind, _) => return Err(syntax(line, &format!("unknown record type {:02X}", kind))),
+            }
+        }
+        Ok(image)
+    }
+
+    /// Parses Motorola S-records: S1-S3 data, S7-S9 start address. Header
+    /// (S0) and count (S5, S6) records are checked and skipped.
+    pub fn from_srecord(text: &str) -> Result<Self, LoadError> {
+        let mut image = Image::default();
+        for (index, record) in text.lines().enumerate() {
+            let line = index + 1;
+            let record = record.trim();
+            if record.is_empty() {
+                continue;
+            }
+            let rest = record.strip_prefix('S').ok_or_else(|| syntax(line, "expected `S`"))?;
+            let kind = rest.chars().next().ok_or_else(|| syntax(line, "missing record type"))?;
+            let bytes = record_bytes(line, &rest[kind.len_utf8()..])?;
+            if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+                return Err(syntax(line, "record length does not match its byte count"));
+            }
+            if bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0xff {
+                return Err(syntax(line, "bad checksum"));
+            }
+            let address_len = match kind {
+                '0' | '1' | '5' | '9' => 2,
+                '2' | '6' | '8' => 3,
+                '3' | '7' => 4,
+                _ => return Err(syntax(line, &format!("unknown record type S{}", kind))),
+            };
+            if bytes.len() < address_len + 2 {
+                return Err(syntax(line, "record too short for its address"));
+            }
+            let address = big_endian(&bytes[1..=address_len]);
+            let data = &bytes[address_len + 1..bytes.len() - 1];
+            match kind {
+                '1' | '2' | '3' => image.push(address, data)?,
+                '7' | '8' | '9' => image.start = Some(start_address(line, address)?),
+                _ => {}
+            }
+        }
+        Ok(image)
+    }
+}
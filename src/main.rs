@@ -1,3 +1,266 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, execute};
+
+use rs_nes::asm;
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::debugger::{Debugger, Reply};
+use rs_nes::disasm::Symbols;
+use rs_nes::gdb::GdbStub;
+use rs_nes::loader::Image;
+use rs_nes::sandbox::Sandbox;
+use rs_nes::snake::{Snake, Status};
+
+const USAGE: &str = "\
+usage: rs_nes debug FILE [--org ADDR] [--cpu nmos|2a03|65c02]
+       rs_nes gdb FILE [--org ADDR] [--cpu nmos|2a03|65c02] [--port N]
+       rs_nes run6502 FILE [--org ADDR] [--cpu nmos|2a03|65c02] [--rom]
+       rs_nes snake [--seed N] [--headless FRAMES [--keys KEYS]]
+
+FILE is 6502 assembly if it ends in .s or .asm, Intel HEX if .hex or .ihx,
+Motorola S-records if .srec, .s19, .s28, .s37 or .mot, and otherwise a raw
+binary loaded at --org (default $8000). Execution starts at the file's start
+address if it has one, else at the reset vector if the file sets $FFFC-$FFFD,
+else at the first loaded byte.
+`gdb` waits for a GDB remote connection on 127.0.0.1 (default port 1234).
+`run6502` runs FILE with putchar at $F001, getchar at $F004 and an exit
+port at $F00F; --rom makes the loaded bytes read-only.
+`snake` plays the tutorial's snake game; steer with w, a, s, d or the arrow
+keys, q quits. --headless prints FRAMES frames as text instead, pressing
+the Nth character of KEYS before frame N (`.` for none).";
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("`{}` is not a hex address", text))
+}
+
+fn parse_variant(text: &str) -> Result<CpuVariant, String> {
+    match text {
+        "nmos" | "6502" => Ok(CpuVariant::Nmos6502),
+        "2a03" => Ok(CpuVariant::Ricoh2A03),
+        "65c02" | "cmos" => Ok(CpuVariant::Cmos65C02),
+        _ => Err(format!("unknown CPU `{}`", text)),
+    }
+}
+
+struct Options {
+    file: String,
+    origin: u16,
+    variant: CpuVariant,
+    port: u16,
+    rom: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut file = None;
+    let mut origin = 0x8000;
+    let mut variant = CpuVariant::Nmos6502;
+    let mut port = 1234;
+    let mut rom = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--org" => origin = parse_address(args.next().ok_or("--org needs an address")?)?,
+            "--cpu" => variant = parse_variant(args.next().ok_or("--cpu needs a name")?)?,
+            "--port" => {
+                let text = args.next().ok_or("--port needs a number")?;
+                port = text.parse().map_err(|_| format!("`{}` is not a port number", text))?;
+            }
+            "--rom" => rom = true,
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let file = file.ok_or(USAGE)?;
+    Ok(Options {
+        file,
+        origin,
+        variant,
+        port,
+        rom,
+    })
+}
+
+/// Reads the program, assembling or parsing it as its extension says, and
+/// returns it with its labels.
+fn load_program(options: &Options) -> Result<(Image, Symbols), String> {
+    let file = &options.file;
+    let context = |err: &dyn std::fmt::Display| format!("{}: {}", file, err);
+    let extension = file.rsplit_once('.').map_or("", |(_, extension)| extension);
+    let read_text = || fs::read_to_string(file).map_err(|err| context(&err));
+    let mut symbols = Symbols::new();
+    let image = match extension {
+        "s" | "asm" => {
+            let assembly = asm::assemble(options.variant, &read_text()?).map_err(|err| context(&err))?;
+            symbols = assembly.symbols();
+            Image::raw(assembly.origin, assembly.bytes)
+        }
+        "hex" | "ihx" => Image::from_intel_hex(&read_text()?),
+        "srec" | "s19" | "s28" | "s37" | "mot" => Image::from_srecord(&read_text()?),
+        _ => Image::raw(options.origin, fs::read(file).map_err(|err| context(&err))?),
+    }
+    .map_err(|err| context(&err))?;
+    Ok((image, symbols))
+}
+
+/// Where execution starts: the file's start address, else the reset vector if
+/// the image sets it, else the first segment.
+fn entry(image: &Image) -> Result<u16, String> {
+    image.entry().ok_or_else(|| "the file holds no data".to_string())
+}
+
+fn debug(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let (image, symbols) = load_program(&options)?;
+    let mut debugger = Debugger::new(options.variant);
+    debugger.symbols = symbols;
+    debugger.cpu.load_image(&image).map_err(|err| err.to_string())?;
+    debugger.cpu.reset();
+    debugger.cpu.program_counter = entry(&image)?;
+
+    println!("{}", debugger.location());
+    let stdin = io::stdin();
+    loop {
+        print!("(rs_nes) ");
+        io::stdout().flush().map_err(|err| err.to_string())?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Ok(());
+        }
+        match debugger.command(&line) {
+            Reply::Text(text) if text.is_empty() => {}
+            Reply::Text(text) => println!("{}", text),
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+fn gdb(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let (image, _) = load_program(&options)?;
+    let mut cpu = CPU::with_variant(options.variant);
+    cpu.load_image(&image).map_err(|err| err.to_string())?;
+    cpu.reset();
+    cpu.program_counter = entry(&image)?;
+
+    eprintln!("waiting for gdb on 127.0.0.1:{}", options.port);
+    GdbStub::new(cpu)
+        .listen(("127.0.0.1", options.port))
+        .map_err(|err| err.to_string())
+}
+
+fn run6502(args: &[String]) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let (image, _) = load_program(&options)?;
+    let mut sandbox = Sandbox::new(options.variant, io::stdin(), io::stdout());
+    sandbox.load(&image).map_err(|err| err.to_string())?;
+    if options.rom {
+        for segment in image.segments.iter().filter(|segment| !segment.bytes.is_empty()) {
+            sandbox.protect(segment.origin..=(segment.end() - 1) as u16);
+        }
+    }
+    let code = sandbox.run().map_err(|err| err.to_string())?;
+    process::exit(code as i32);
+}
+
+/// Plays snake in the terminal until the game ends or q is pressed.
+fn play_snake(snake: &mut Snake) -> io::Result<Status> {
+    let mut stdout = io::stdout();
+    loop {
+        if event::poll(Duration::from_millis(60))? {
+            if let Event::Key(key) = event::read()? {
+                let pressed = match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(Status::Running),
+                    KeyCode::Char(c) if c.is_ascii() => c as u8,
+                    KeyCode::Up => b'w',
+                    KeyCode::Left => b'a',
+                    KeyCode::Down => b's',
+                    KeyCode::Right => b'd',
+                    _ => continue,
+                };
+                snake.press(pressed);
+            }
+        }
+        let status = snake.frame().map_err(io::Error::other)?;
+        write!(stdout, "\x1b[H{}", snake.render_ansi())?;
+        stdout.flush()?;
+        if status == Status::GameOver {
+            return Ok(status);
+        }
+    }
+}
+
+fn snake(args: &[String]) -> Result<(), String> {
+    let mut seed = 1;
+    let mut headless = None;
+    let mut keys = String::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |what: &str| args.next().cloned().ok_or(format!("{} needs {}", arg, what));
+        match arg.as_str() {
+            "--seed" => {
+                let text = value("a number")?;
+                seed = text.parse().map_err(|_| format!("`{}` is not a seed", text))?;
+            }
+            "--headless" => {
+                let text = value("a frame count")?;
+                headless = Some(text.parse::<usize>().map_err(|_| format!("`{}` is not a frame count", text))?);
+            }
+            "--keys" => keys = value("a string of keys")?,
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    let mut snake = Snake::new(seed);
+
+    if let Some(frames) = headless {
+        let mut keys = keys.bytes();
+        for frame in 1..=frames {
+            match keys.next() {
+                Some(b'.') | None => {}
+                Some(key) => snake.press(key),
+            }
+            let status = snake.frame().map_err(|err| err.to_string())?;
+            println!("frame {}\n{}", frame, snake.render_text());
+            if status == Status::GameOver {
+                println!("game over");
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode().map_err(|err| err.to_string())?;
+    let result = execute!(stdout, cursor::Hide, terminal::Clear(ClearType::All)).and_then(|_| play_snake(&mut snake));
+    // put the terminal back even if the game failed
+    let _ = execute!(stdout, cursor::Show);
+    let _ = terminal::disable_raw_mode();
+    if result.map_err(|err| err.to_string())? == Status::GameOver {
+        println!("game over");
+    }
+    Ok(())
+}
+
 fn main () {
-    println!("NES TIME");
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("debug") => debug(&args[1..]),
+        Some("gdb") => gdb(&args[1..]),
+        Some("run6502") => run6502(&args[1..]),
+        Some("snake") => snake(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(2);
+    }
+}
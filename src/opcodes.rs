@@ -1,58 +1,607 @@
-use crate::cpu::AddressingMode;
-use std::collections::HashMap;
+use crate::cpu::{AddressingMode, CpuVariant};
 
+/// How an instruction uses the memory its addressing mode points at, which
+/// decides the bus accesses it makes after the address is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+    /// Implied, accumulator, stack, jump and branch instructions.
+    Other,
+}
+
+/// The operation an opcode performs, so the CPU can dispatch without comparing mnemonics.
+/// RMB, SMB, BBR and BBS take their bit number from the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC,
+    CLD, CLI, CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP,
+    JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL, ROR, RTI,
+    RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+
+    /* Unofficial NMOS */
+    ALR, ANC, ARR, AXS, DCP, ISB, JAM, LAS, LAX, LXA, RLA, RRA, SAX, SHA,
+    SHX, SHY, SLO, SRE, TAS, XAA,
+
+    /* 65C02 */
+    BRA, PHX, PHY, PLX, PLY, STZ, TRB, TSB, RMB, SMB, BBR, BBS,
+}
+
+const INSTRUCTIONS: [(&str, Instruction); 88] = [
+    ("ADC", Instruction::ADC), ("AND", Instruction::AND), ("ASL", Instruction::ASL),
+    ("BCC", Instruction::BCC), ("BCS", Instruction::BCS), ("BEQ", Instruction::BEQ),
+    ("BIT", Instruction::BIT), ("BMI", Instruction::BMI), ("BNE", Instruction::BNE),
+    ("BPL", Instruction::BPL), ("BRK", Instruction::BRK), ("BVC", Instruction::BVC),
+    ("BVS", Instruction::BVS), ("CLC", Instruction::CLC), ("CLD", Instruction::CLD),
+    ("CLI", Instruction::CLI), ("CLV", Instruction::CLV), ("CMP", Instruction::CMP),
+    ("CPX", Instruction::CPX), ("CPY", Instruction::CPY), ("DEC", Instruction::DEC),
+    ("DEX", Instruction::DEX), ("DEY", Instruction::DEY), ("EOR", Instruction::EOR),
+    ("INC", Instruction::INC), ("INX", Instruction::INX), ("INY", Instruction::INY),
+    ("JMP", Instruction::JMP), ("JSR", Instruction::JSR), ("LDA", Instruction::LDA),
+    ("LDX", Instruction::LDX), ("LDY", Instruction::LDY), ("LSR", Instruction::LSR),
+    ("NOP", Instruction::NOP), ("ORA", Instruction::ORA), ("PHA", Instruction::PHA),
+    ("PHP", Instruction::PHP), ("PLA", Instruction::PLA), ("PLP", Instruction::PLP),
+    ("ROL", Instruction::ROL), ("ROR", Instruction::ROR), ("RTI", Instruction::RTI),
+    ("RTS", Instruction::RTS), ("SBC", Instruction::SBC), ("SEC", Instruction::SEC),
+    ("SED", Instruction::SED), ("SEI", Instruction::SEI), ("STA", Instruction::STA),
+    ("STX", Instruction::STX), ("STY", Instruction::STY), ("TAX", Instruction::TAX),
+    ("TAY", Instruction::TAY), ("TSX", Instruction::TSX), ("TXA", Instruction::TXA),
+    ("TXS", Instruction::TXS), ("TYA", Instruction::TYA),
+    ("ALR", Instruction::ALR), ("ANC", Instruction::ANC), ("ARR", Instruction::ARR),
+    ("AXS", Instruction::AXS), ("DCP", Instruction::DCP), ("ISB", Instruction::ISB),
+    ("JAM", Instruction::JAM), ("LAS", Instruction::LAS), ("LAX", Instruction::LAX),
+    ("LXA", Instruction::LXA), ("RLA", Instruction::RLA), ("RRA", Instruction::RRA),
+    ("SAX", Instruction::SAX), ("SHA", Instruction::SHA), ("SHX", Instruction::SHX),
+    ("SHY", Instruction::SHY), ("SLO", Instruction::SLO), ("SRE", Instruction::SRE),
+    ("TAS", Instruction::TAS), ("XAA", Instruction::XAA),
+    ("BRA", Instruction::BRA), ("PHX", Instruction::PHX), ("PHY", Instruction::PHY),
+    ("PLX", Instruction::PLX), ("PLY", Instruction::PLY), ("STZ", Instruction::STZ),
+    ("TRB", Instruction::TRB), ("TSB", Instruction::TSB), ("RMB", Instruction::RMB),
+    ("SMB", Instruction::SMB), ("BBR", Instruction::BBR), ("BBS", Instruction::BBS),
+];
+
+impl Instruction {
+    /// Looks up a mnemonic by its first three letters, so "RMB3" is `RMB`.
+    const fn from_mnemonic(mnemonic: &str) -> Instruction {
+        let name = mnemonic.as_bytes();
+        let mut i = 0;
+        while i < INSTRUCTIONS.len() {
+            let (candidate, instruction) = INSTRUCTIONS[i];
+            let candidate = candidate.as_bytes();
+            if name[0] == candidate[0] && name[1] == candidate[1] && name[2] == candidate[2] {
+                return instruction;
+            }
+            i += 1;
+        }
+        panic!("unknown mnemonic");
+    }
+
+    const fn access(self, mode: AddressingMode) -> Access {
+        match mode {
+            AddressingMode::Accumulator
+            | AddressingMode::NoneAddressing
+            | AddressingMode::Relative
+            | AddressingMode::Indirect => return Access::Other,
+            _ => {}
+        }
+
+        match self {
+            Instruction::JMP => Access::Other,
+            Instruction::STA
+            | Instruction::STX
+            | Instruction::STY
+            | Instruction::STZ
+            | Instruction::SAX
+            | Instruction::SHA
+            | Instruction::SHX
+            | Instruction::SHY
+            | Instruction::TAS => Access::Write,
+            Instruction::ASL
+            | Instruction::LSR
+            | Instruction::ROL
+            | Instruction::ROR
+            | Instruction::INC
+            | Instruction::DEC
+            | Instruction::SLO
+            | Instruction::RLA
+            | Instruction::SRE
+            | Instruction::RRA
+            | Instruction::DCP
+            | Instruction::ISB
+            | Instruction::TSB
+            | Instruction::TRB
+            | Instruction::RMB
+            | Instruction::SMB => Access::ReadModifyWrite,
+            _ => Access::Read,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct OpCode {
     pub code: u8,
     pub mnemonic: &'static str,
     pub len: u8,
     pub cycles: u8,
     pub mode: AddressingMode,
+    /// Undocumented opcodes; tracers print them with a `*` prefix like nestest.log.
+    pub unofficial: bool,
+    pub instruction: Instruction,
+    pub access: Access,
 }
 
 impl OpCode {
-    fn new(code: u8, mnemonic: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
+    const fn new(code: u8, mnemonic: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
+        let instruction = Instruction::from_mnemonic(mnemonic);
         OpCode {
-            code: code,
-            mnemonic: mnemonic,
-            len: len,
-            cycles: cycles,
-            mode: mode,
+            code,
+            mnemonic,
+            len,
+            cycles,
+            mode,
+            unofficial: false,
+            instruction,
+            access: instruction.access(mode),
         }
     }
+
+    const fn unofficial(code: u8, mnemonic: &'static str, len: u8, cycles: u8, mode: AddressingMode) -> Self {
+        let mut opcode = OpCode::new(code, mnemonic, len, cycles, mode);
+        opcode.unofficial = true;
+        opcode
+    }
+
+    /// Opcodes left undefined on the 65C02 are NOPs with fixed lengths and timings.
+    const fn cmos_nop(code: u8) -> Self {
+        let (len, cycles, mode) = match code {
+            0x44 => (2, 3, AddressingMode::ZeroPage),
+            0x54 | 0xd4 | 0xf4 => (2, 4, AddressingMode::ZeroPageX),
+            0x5c => (3, 8, AddressingMode::Absolute),
+            0xdc | 0xfc => (3, 4, AddressingMode::Absolute),
+            _ if code & 0x0f == 0x02 => (2, 2, AddressingMode::Immediate),
+            _ => (1, 1, AddressingMode::NoneAddressing),
+        };
+        OpCode::unofficial(code, "NOP", len, cycles, mode)
+    }
 }
 
+pub const CPU_OPS_CODES: &[OpCode] = &[
+    OpCode::new(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
+    OpCode::new(0xea, "NOP", 1, 2, AddressingMode::NoneAddressing),
+
+    /* Arithmetic */
+    OpCode::new(0x69, "ADC", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x65, "ADC", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x75, "ADC", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x6d, "ADC", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x7d, "ADC", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x79, "ADC", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0x61, "ADC", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0x71, "ADC", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0xe9, "SBC", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xe5, "SBC", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xf5, "SBC", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0xed, "SBC", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xfd, "SBC", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0xf9, "SBC", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0xe1, "SBC", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0xf1, "SBC", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0x29, "AND", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x25, "AND", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x35, "AND", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x2d, "AND", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x3d, "AND", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x39, "AND", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0x21, "AND", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0x31, "AND", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0x49, "EOR", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x45, "EOR", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x55, "EOR", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x4d, "EOR", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x5d, "EOR", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x59, "EOR", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0x41, "EOR", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0x51, "EOR", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0x09, "ORA", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x05, "ORA", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x15, "ORA", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x0d, "ORA", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x1d, "ORA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x19, "ORA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0x01, "ORA", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0x11, "ORA", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    /* Shifts */
+    OpCode::new(0x0a, "ASL", 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x06, "ASL", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x16, "ASL", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0x0e, "ASL", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x1e, "ASL", 3, 7, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x4a, "LSR", 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x46, "LSR", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x56, "LSR", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0x4e, "LSR", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x5e, "LSR", 3, 7, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x2a, "ROL", 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x26, "ROL", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x36, "ROL", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0x2e, "ROL", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x3e, "ROL", 3, 7, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x6a, "ROR", 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x66, "ROR", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x76, "ROR", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0x6e, "ROR", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x7e, "ROR", 3, 7, AddressingMode::AbsoluteX),
+
+    /* Increments and decrements */
+    OpCode::new(0xe6, "INC", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0xf6, "INC", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0xee, "INC", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0xfe, "INC", 3, 7, AddressingMode::AbsoluteX),
+
+    OpCode::new(0xe8, "INX", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xc8, "INY", 1, 2, AddressingMode::NoneAddressing),
+
+    OpCode::new(0xc6, "DEC", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0xd6, "DEC", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::new(0xce, "DEC", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0xde, "DEC", 3, 7, AddressingMode::AbsoluteX),
+
+    OpCode::new(0xca, "DEX", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x88, "DEY", 1, 2, AddressingMode::NoneAddressing),
+
+    /* Compares */
+    OpCode::new(0xc9, "CMP", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xc5, "CMP", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xd5, "CMP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0xcd, "CMP", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xdd, "CMP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0xd9, "CMP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0xc1, "CMP", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0xd1, "CMP", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0xe0, "CPX", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xe4, "CPX", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xec, "CPX", 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0xc0, "CPY", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xc4, "CPY", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xcc, "CPY", 3, 4, AddressingMode::Absolute),
+
+    OpCode::new(0x24, "BIT", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x2c, "BIT", 3, 4, AddressingMode::Absolute),
+
+    /* Branching */
+    OpCode::new(0x4c, "JMP", 3, 3, AddressingMode::Absolute),
+    OpCode::new(0x6c, "JMP", 3, 5, AddressingMode::Indirect), //with the 6502 page wrap bug
+
+    OpCode::new(0x20, "JSR", 3, 6, AddressingMode::NoneAddressing),
+    OpCode::new(0x60, "RTS", 1, 6, AddressingMode::NoneAddressing),
+    OpCode::new(0x40, "RTI", 1, 6, AddressingMode::NoneAddressing),
+
+    OpCode::new(0xd0, "BNE", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0x70, "BVS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0x50, "BVC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0x30, "BMI", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0xf0, "BEQ", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0xb0, "BCS", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0x90, "BCC", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+    OpCode::new(0x10, "BPL", 2, 2 /*(+1 if branch succeeds +2 if to a new page)*/, AddressingMode::Relative),
+
+    /* Flags */
+    OpCode::new(0xd8, "CLD", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x58, "CLI", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xb8, "CLV", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x18, "CLC", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x38, "SEC", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x78, "SEI", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xf8, "SED", 1, 2, AddressingMode::NoneAddressing),
+
+    /* Transfers */
+    OpCode::new(0xaa, "TAX", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xa8, "TAY", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0xba, "TSX", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x8a, "TXA", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x9a, "TXS", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x98, "TYA", 1, 2, AddressingMode::NoneAddressing),
+
+    /* Stores, Loads */
+    OpCode::new(0xa9, "LDA", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xa5, "LDA", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xb5, "LDA", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0xad, "LDA", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xbd, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0xb9, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::new(0xa1, "LDA", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0xb1, "LDA", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::new(0xa2, "LDX", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xa6, "LDX", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xb6, "LDX", 2, 4, AddressingMode::ZeroPageY),
+    OpCode::new(0xae, "LDX", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xbe, "LDX", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+
+    OpCode::new(0xa0, "LDY", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0xa4, "LDY", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0xb4, "LDY", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0xac, "LDY", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0xbc, "LDY", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
 
-lazy_static! {
-    pub static ref CPU_OPS_CODES: Vec<OpCode> = vec![
-        OpCode::new(0x00, "BRK", 1, 7, AddressingMode::NoneAddressing),
-        OpCode::new(0xaa, "TAX", 1, 2, AddressingMode::NoneAddressing),
-        OpCode::new(0xe8, "INX", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::new(0x85, "STA", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x95, "STA", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x8d, "STA", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x9d, "STA", 3, 5, AddressingMode::AbsoluteX),
+    OpCode::new(0x99, "STA", 3, 5, AddressingMode::AbsoluteY),
+    OpCode::new(0x81, "STA", 2, 6, AddressingMode::IndirectX),
+    OpCode::new(0x91, "STA", 2, 6, AddressingMode::IndirectY),
 
-        OpCode::new(0xa9, "LDA", 2, 2, AddressingMode::Immediate),
-        OpCode::new(0xa5, "LDA", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0xb5, "LDA", 2, 4, AddressingMode::ZeroPageX),
-        OpCode::new(0xad, "LDA", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0xbd, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
-        OpCode::new(0xb9, "LDA", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
-        OpCode::new(0xa1, "LDA", 2, 6, AddressingMode::IndirectX),
-        OpCode::new(0xb1, "LDA", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+    OpCode::new(0x86, "STX", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x96, "STX", 2, 4, AddressingMode::ZeroPageY),
+    OpCode::new(0x8e, "STX", 3, 4, AddressingMode::Absolute),
 
-        OpCode::new(0x85, "STA", 2, 3, AddressingMode::ZeroPage),
-        OpCode::new(0x95, "STA", 2, 4, AddressingMode::ZeroPageX),
-        OpCode::new(0x8d, "STA", 3, 4, AddressingMode::Absolute),
-        OpCode::new(0x9d, "STA", 3, 5, AddressingMode::AbsoluteX),
-        OpCode::new(0x99, "STA", 3, 5, AddressingMode::AbsoluteY),
-        OpCode::new(0x81, "STA", 2, 6, AddressingMode::IndirectX),
-        OpCode::new(0x91, "STA", 2, 6, AddressingMode::IndirectY),
+    OpCode::new(0x84, "STY", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x94, "STY", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x8c, "STY", 3, 4, AddressingMode::Absolute),
 
-    ];
+    /* Stack */
+    OpCode::new(0x48, "PHA", 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x68, "PLA", 1, 4, AddressingMode::NoneAddressing),
+    OpCode::new(0x08, "PHP", 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x28, "PLP", 1, 4, AddressingMode::NoneAddressing),
 
+    /* Unofficial opcodes */
+    OpCode::unofficial(0x1a, "NOP", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x3a, "NOP", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x5a, "NOP", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x7a, "NOP", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0xda, "NOP", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0xfa, "NOP", 1, 2, AddressingMode::NoneAddressing),
 
-    pub static ref OPCODES_MAP: HashMap<u8, &'static OpCode> = {
-        let mut map = HashMap::new();
-        for cpuop in &*CPU_OPS_CODES {
-            map.insert(cpuop.code, cpuop);
+    OpCode::unofficial(0x80, "NOP", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x82, "NOP", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x89, "NOP", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0xc2, "NOP", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0xe2, "NOP", 2, 2, AddressingMode::Immediate),
+
+    OpCode::unofficial(0x04, "NOP", 2, 3, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x44, "NOP", 2, 3, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x64, "NOP", 2, 3, AddressingMode::ZeroPage),
+
+    OpCode::unofficial(0x14, "NOP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x34, "NOP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x54, "NOP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x74, "NOP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0xd4, "NOP", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0xf4, "NOP", 2, 4, AddressingMode::ZeroPageX),
+
+    OpCode::unofficial(0x0c, "NOP", 3, 4, AddressingMode::Absolute),
+
+    OpCode::unofficial(0x1c, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x3c, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x5c, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x7c, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0xdc, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0xfc, "NOP", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+
+    OpCode::unofficial(0xa7, "LAX", 2, 3, AddressingMode::ZeroPage),
+    OpCode::unofficial(0xb7, "LAX", 2, 4, AddressingMode::ZeroPageY),
+    OpCode::unofficial(0xaf, "LAX", 3, 4, AddressingMode::Absolute),
+    OpCode::unofficial(0xbf, "LAX", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0xa3, "LAX", 2, 6, AddressingMode::IndirectX),
+    OpCode::unofficial(0xb3, "LAX", 2, 5/*+1 if page crossed*/, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x87, "SAX", 2, 3, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x97, "SAX", 2, 4, AddressingMode::ZeroPageY),
+    OpCode::unofficial(0x8f, "SAX", 3, 4, AddressingMode::Absolute),
+    OpCode::unofficial(0x83, "SAX", 2, 6, AddressingMode::IndirectX),
+
+    OpCode::unofficial(0xeb, "SBC", 2, 2, AddressingMode::Immediate),
+
+    OpCode::unofficial(0xc7, "DCP", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0xd7, "DCP", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0xcf, "DCP", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0xdf, "DCP", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0xdb, "DCP", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0xc3, "DCP", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0xd3, "DCP", 2, 8, AddressingMode::IndirectY),
+
+    // also known as ISC; nestest.log calls it ISB
+    OpCode::unofficial(0xe7, "ISB", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0xf7, "ISB", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0xef, "ISB", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0xff, "ISB", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0xfb, "ISB", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0xe3, "ISB", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0xf3, "ISB", 2, 8, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x07, "SLO", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x17, "SLO", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x0f, "SLO", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0x1f, "SLO", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x1b, "SLO", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x03, "SLO", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0x13, "SLO", 2, 8, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x27, "RLA", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x37, "RLA", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x2f, "RLA", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0x3f, "RLA", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x3b, "RLA", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x23, "RLA", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0x33, "RLA", 2, 8, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x47, "SRE", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x57, "SRE", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x4f, "SRE", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0x5f, "SRE", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x5b, "SRE", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x43, "SRE", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0x53, "SRE", 2, 8, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x67, "RRA", 2, 5, AddressingMode::ZeroPage),
+    OpCode::unofficial(0x77, "RRA", 2, 6, AddressingMode::ZeroPageX),
+    OpCode::unofficial(0x6f, "RRA", 3, 6, AddressingMode::Absolute),
+    OpCode::unofficial(0x7f, "RRA", 3, 7, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x7b, "RRA", 3, 7, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x63, "RRA", 2, 8, AddressingMode::IndirectX),
+    OpCode::unofficial(0x73, "RRA", 2, 8, AddressingMode::IndirectY),
+
+    OpCode::unofficial(0x0b, "ANC", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x2b, "ANC", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x4b, "ALR", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x6b, "ARR", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0xcb, "AXS", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0xbb, "LAS", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteY),
+
+    /* Unstable: the exact results depend on the chip, these follow the commonly observed behaviour */
+    OpCode::unofficial(0x8b, "XAA", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0xab, "LXA", 2, 2, AddressingMode::Immediate),
+    OpCode::unofficial(0x9f, "SHA", 3, 5, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x93, "SHA", 2, 6, AddressingMode::IndirectY),
+    OpCode::unofficial(0x9e, "SHX", 3, 5, AddressingMode::AbsoluteY),
+    OpCode::unofficial(0x9c, "SHY", 3, 5, AddressingMode::AbsoluteX),
+    OpCode::unofficial(0x9b, "TAS", 3, 5, AddressingMode::AbsoluteY),
+
+    /* JAM (KIL): halts the CPU until reset */
+    OpCode::unofficial(0x02, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x12, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x22, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x32, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x42, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x52, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x62, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x72, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0x92, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0xb2, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0xd2, "JAM", 1, 2, AddressingMode::NoneAddressing),
+    OpCode::unofficial(0xf2, "JAM", 1, 2, AddressingMode::NoneAddressing),
+];
+
+/// Opcodes added or changed by the 65C02, except the Rockwell bit instructions
+/// that `cmos_table` fills in.
+pub const CMOS_OPS_CODES: &[OpCode] = &[
+    OpCode::new(0x6c, "JMP", 3, 6, AddressingMode::Indirect), //page bug fixed
+    OpCode::new(0x7c, "JMP", 3, 6, AddressingMode::NoneAddressing), //AddressingMode:Indirect,X
+
+    OpCode::new(0x1e, "ASL", 3, 6/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x5e, "LSR", 3, 6/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x3e, "ROL", 3, 6/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+    OpCode::new(0x7e, "ROR", 3, 6/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x12, "ORA", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0x32, "AND", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0x52, "EOR", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0x72, "ADC", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0x92, "STA", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0xb2, "LDA", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0xd2, "CMP", 2, 5, AddressingMode::ZeroPageIndirect),
+    OpCode::new(0xf2, "SBC", 2, 5, AddressingMode::ZeroPageIndirect),
+
+    OpCode::new(0x89, "BIT", 2, 2, AddressingMode::Immediate),
+    OpCode::new(0x34, "BIT", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x3c, "BIT", 3, 4/*+1 if page crossed*/, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x1a, "INC", 1, 2, AddressingMode::Accumulator),
+    OpCode::new(0x3a, "DEC", 1, 2, AddressingMode::Accumulator),
+
+    OpCode::new(0x80, "BRA", 2, 2 /*(+1 always taken +1 if to a new page)*/, AddressingMode::Relative),
+
+    OpCode::new(0xda, "PHX", 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0xfa, "PLX", 1, 4, AddressingMode::NoneAddressing),
+    OpCode::new(0x5a, "PHY", 1, 3, AddressingMode::NoneAddressing),
+    OpCode::new(0x7a, "PLY", 1, 4, AddressingMode::NoneAddressing),
+
+    OpCode::new(0x64, "STZ", 2, 3, AddressingMode::ZeroPage),
+    OpCode::new(0x74, "STZ", 2, 4, AddressingMode::ZeroPageX),
+    OpCode::new(0x9c, "STZ", 3, 4, AddressingMode::Absolute),
+    OpCode::new(0x9e, "STZ", 3, 5, AddressingMode::AbsoluteX),
+
+    OpCode::new(0x14, "TRB", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x1c, "TRB", 3, 6, AddressingMode::Absolute),
+    OpCode::new(0x04, "TSB", 2, 5, AddressingMode::ZeroPage),
+    OpCode::new(0x0c, "TSB", 3, 6, AddressingMode::Absolute),
+];
+
+/// Opcodes indexed by their byte, built at compile time.
+pub static NMOS_OPCODES: [OpCode; 256] = nmos_table();
+
+/// The 65C02 keeps the documented NMOS opcodes, applies `CMOS_OPS_CODES` and the
+/// Rockwell bit instructions on top, and turns every remaining byte into a NOP.
+pub static CMOS_OPCODES: [OpCode; 256] = cmos_table();
+
+/// The NMOS list must define each of the 256 opcodes exactly once.
+const fn nmos_table() -> [OpCode; 256] {
+    let mut table = [OpCode::cmos_nop(0); 256];
+    let mut defined = [false; 256];
+    let mut i = 0;
+    while i < CPU_OPS_CODES.len() {
+        let op = CPU_OPS_CODES[i];
+        if defined[op.code as usize] {
+            panic!("opcode defined twice");
+        }
+        defined[op.code as usize] = true;
+        table[op.code as usize] = op;
+        i += 1;
+    }
+    if CPU_OPS_CODES.len() != 256 {
+        panic!("opcode table is incomplete");
+    }
+    table
+}
+
+const fn cmos_table() -> [OpCode; 256] {
+    let mut table = [OpCode::cmos_nop(0); 256];
+    let mut code = 0;
+    while code < 256 {
+        table[code] = OpCode::cmos_nop(code as u8);
+        code += 1;
+    }
+
+    let mut i = 0;
+    while i < CPU_OPS_CODES.len() {
+        let op = CPU_OPS_CODES[i];
+        if !op.unofficial {
+            table[op.code as usize] = op;
         }
-        map
-    };
-}
\ No newline at end of file
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < CMOS_OPS_CODES.len() {
+        let op = CMOS_OPS_CODES[i];
+        table[op.code as usize] = op;
+        i += 1;
+    }
+
+    /* Rockwell bit instructions: RMBn/SMBn zp and BBRn/BBSn zp,rel */
+    const RMB: [&str; 8] = ["RMB0", "RMB1", "RMB2", "RMB3", "RMB4", "RMB5", "RMB6", "RMB7"];
+    const SMB: [&str; 8] = ["SMB0", "SMB1", "SMB2", "SMB3", "SMB4", "SMB5", "SMB6", "SMB7"];
+    const BBR: [&str; 8] = ["BBR0", "BBR1", "BBR2", "BBR3", "BBR4", "BBR5", "BBR6", "BBR7"];
+    const BBS: [&str; 8] = ["BBS0", "BBS1", "BBS2", "BBS3", "BBS4", "BBS5", "BBS6", "BBS7"];
+    let mut bit = 0;
+    while bit < 8 {
+        let row = (bit as u8) << 4;
+        table[(row | 0x07) as usize] = OpCode::new(row | 0x07, RMB[bit], 2, 5, AddressingMode::ZeroPage);
+        table[(0x80 | row | 0x07) as usize] = OpCode::new(0x80 | row | 0x07, SMB[bit], 2, 5, AddressingMode::ZeroPage);
+        table[(row | 0x0f) as usize] = OpCode::new(row | 0x0f, BBR[bit], 3, 5 /*(+1 if branch succeeds +1 if to a new page)*/, AddressingMode::NoneAddressing);
+        table[(0x80 | row | 0x0f) as usize] = OpCode::new(0x80 | row | 0x0f, BBS[bit], 3, 5 /*(+1 if branch succeeds +1 if to a new page)*/, AddressingMode::NoneAddressing);
+        bit += 1;
+    }
+    table
+}
+
+/// The opcode table for a CPU variant. The NMOS 6502 and the 2A03 share one.
+pub fn opcode_table(variant: CpuVariant) -> &'static [OpCode; 256] {
+    match variant {
+        CpuVariant::Nmos6502 | CpuVariant::Ricoh2A03 => &NMOS_OPCODES,
+        CpuVariant::Cmos65C02 => &CMOS_OPCODES,
+    }
+}
@@ -0,0 +1,142 @@
+//! A plain 6502 machine for programs that aren't NES games, such as EhBASIC or
+//! a Forth: RAM everywhere, optional ROM ranges, and a few I/O ports.
+//!
+//! | Port    | Access | Effect                                             |
+//! |---------|--------|----------------------------------------------------|
+//! | `$F001` | write  | writes a character to the output                   |
+//! | `$F004` | read   | reads a character from the input, 0 once it's gone |
+//! | `$F00F` | write  | stops the machine with the byte as the exit code   |
+
+use std::io::{Read, Write};
+use std::ops::RangeInclusive;
+
+use crate::bus::{Bus, FlatRam, HandlerId, IoBus, IoHandler};
+use crate::cpu::{CpuError, CpuVariant, Vector, CPU};
+use crate::loader::{Image, LoadError};
+
+pub const PUTCHAR: u16 = 0xf001;
+pub const GETCHAR: u16 = 0xf004;
+pub const EXIT: u16 = 0xf00f;
+
+/// Flat RAM in which some ranges can be made read-only.
+#[derive(Default)]
+pub struct Memory {
+    ram: FlatRam,
+    rom: Vec<RangeInclusive<u16>>,
+}
+
+impl Bus for Memory {
+    fn read(&mut self, address: u16) -> u8 {
+        self.ram.read(address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if !self.rom.iter().any(|range| range.contains(&address)) {
+            self.ram.write(address, data);
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.ram.peek(address)
+    }
+}
+
+/// The character ports.
+struct Console {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl IoHandler for Console {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        if address != GETCHAR {
+            return None;
+        }
+        let mut byte = [0];
+        match self.input.read(&mut byte) {
+            Ok(1) => Some(byte[0]),
+            _ => Some(0),
+        }
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if address == PUTCHAR {
+            // a program has no way to see a failed write, so drop it like a
+            // disconnected terminal would
+            let _ = self.output.write_all(&[data]).and_then(|_| self.output.flush());
+        }
+    }
+}
+
+#[derive(Default)]
+struct ExitPort {
+    code: Option<u8>,
+}
+
+impl IoHandler for ExitPort {
+    fn write(&mut self, _address: u16, data: u8) {
+        self.code = Some(data);
+    }
+}
+
+pub struct Sandbox {
+    pub cpu: CPU<IoBus<Memory>>,
+    exit: HandlerId,
+}
+
+impl Sandbox {
+    /// A machine whose getchar port reads `input` and putchar port writes
+    /// `output`; the command line wires these to stdin and stdout.
+    pub fn new<R, W>(variant: CpuVariant, input: R, output: W) -> Self
+    where
+        R: Read + 'static,
+        W: Write + 'static,
+    {
+        let mut bus = IoBus::new(Memory::default());
+        let console = Console {
+            input: Box::new(input),
+            output: Box::new(output),
+        };
+        bus.map(PUTCHAR..=GETCHAR, console);
+        let exit = bus.map(EXIT..=EXIT, ExitPort::default());
+        Sandbox {
+            cpu: CPU::with_bus(variant, bus),
+            exit,
+        }
+    }
+
+    /// Loads `image`, points the reset vector at its entry and resets.
+    pub fn load(&mut self, image: &Image) -> Result<(), LoadError> {
+        self.cpu.load_image(image)?;
+        if let Some(entry) = image.entry() {
+            self.cpu.set_vector(Vector::Reset, entry);
+        }
+        self.cpu.reset();
+        Ok(())
+    }
+
+    /// Makes `range` read-only. Writes to it are ignored from now on.
+    pub fn protect(&mut self, range: RangeInclusive<u16>) {
+        self.cpu.bus.inner.rom.push(range);
+    }
+
+    /// Runs one instruction, returning the exit code once the program has
+    /// written one.
+    pub fn step(&mut self) -> Result<Option<u8>, CpuError> {
+        self.cpu.step()?;
+        Ok(self.exit_code())
+    }
+
+    /// Runs until the program writes to the exit port.
+    pub fn run(&mut self) -> Result<u8, CpuError> {
+        loop {
+            if let Some(code) = self.step()? {
+                return Ok(code);
+            }
+        }
+    }
+
+    pub fn exit_code(&self) -> Option<u8> {
+        self.cpu.bus.handler::<ExitPort>(self.exit).and_then(|exit| exit.code)
+    }
+}
@@ -0,0 +1,361 @@
+//! The snake game machine from the classic 6502 tutorial: a random byte at
+//! $FE, the last key pressed at $FF, and a 32x32 screen at $0200-$05FF with
+//! one byte per pixel.
+//!
+//! The game is Nick Morgan's snake from easy6502, built with our assembler.
+
+use std::fmt::Write;
+
+use crate::asm;
+use crate::bus::{IoBus, IoHandler};
+use crate::cpu::{CpuError, CpuVariant, Vector, CPU};
+
+pub const RANDOM: u16 = 0xfe;
+pub const LAST_KEY: u16 = 0xff;
+pub const SCREEN: u16 = 0x0200;
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+
+/// Steer with w, a, s and d.
+pub const SOURCE: &str = "
+        .org $0600
+appleL          = $00   ; screen location of the apple
+appleH          = $01
+snakeDirection  = $02
+snakeLength     = $03   ; in bytes, two per segment
+snakeHeadL      = $10   ; screen location of the head
+snakeHeadH      = $11
+snakeBodyStart  = $12   ; then the body segments
+
+movingUp        = 1
+movingRight     = 2
+movingDown      = 4
+movingLeft      = 8
+
+sysRandom       = $fe
+sysLastKey      = $ff
+
+        JSR init
+        JSR loop
+
+init:   JSR initSnake
+        JSR generateApplePosition
+        RTS
+
+initSnake:
+        LDA #movingRight
+        STA snakeDirection
+        LDA #4                  ; two segments
+        STA snakeLength
+        LDA #$11
+        STA snakeHeadL
+        LDA #$10
+        STA snakeBodyStart
+        LDA #$0f
+        STA $14
+        LDA #$04
+        STA snakeHeadH
+        STA $13
+        STA $15
+        RTS
+
+generateApplePosition:
+        LDA sysRandom
+        STA appleL
+        LDA sysRandom           ; a page from 2 to 5
+        AND #$03
+        CLC
+        ADC #2
+        STA appleH
+        RTS
+
+loop:   JSR readKeys
+        JSR checkCollision
+        JSR updateSnake
+        JSR drawApple
+        JSR drawSnake
+        JSR spinWheels
+        JMP loop
+
+readKeys:
+        LDA sysLastKey
+        CMP #'w'
+        BEQ upKey
+        CMP #'d'
+        BEQ rightKey
+        CMP #'s'
+        BEQ downKey
+        CMP #'a'
+        BEQ leftKey
+        RTS
+upKey:  LDA #movingDown         ; can't turn back on itself
+        BIT snakeDirection
+        BNE illegalMove
+        LDA #movingUp
+        STA snakeDirection
+        RTS
+rightKey:
+        LDA #movingLeft
+        BIT snakeDirection
+        BNE illegalMove
+        LDA #movingRight
+        STA snakeDirection
+        RTS
+downKey:
+        LDA #movingUp
+        BIT snakeDirection
+        BNE illegalMove
+        LDA #movingDown
+        STA snakeDirection
+        RTS
+leftKey:
+        LDA #movingRight
+        BIT snakeDirection
+        BNE illegalMove
+        LDA #movingLeft
+        STA snakeDirection
+        RTS
+illegalMove:
+        RTS
+
+checkCollision:
+        JSR checkAppleCollision
+        JSR checkSnakeCollision
+        RTS
+
+checkAppleCollision:
+        LDA appleL
+        CMP snakeHeadL
+        BNE doneCheckingAppleCollision
+        LDA appleH
+        CMP snakeHeadH
+        BNE doneCheckingAppleCollision
+        INC snakeLength         ; eat the apple and grow
+        INC snakeLength
+        JSR generateApplePosition
+doneCheckingAppleCollision:
+        RTS
+
+checkSnakeCollision:
+        LDX #2                  ; start with the second segment
+snakeCollisionLoop:
+        LDA snakeHeadL,X
+        CMP snakeHeadL
+        BNE continueCollisionLoop
+maybeCollided:
+        LDA snakeHeadH,X
+        CMP snakeHeadH
+        BEQ didCollide
+continueCollisionLoop:
+        INX
+        INX
+        CPX snakeLength
+        BEQ didntCollide
+        JMP snakeCollisionLoop
+didCollide:
+        JMP gameOver
+didntCollide:
+        RTS
+
+updateSnake:
+        LDX snakeLength
+        DEX
+        TXA
+updateLoop:
+        LDA snakeHeadL,X        ; shift the body along
+        STA snakeBodyStart,X
+        DEX
+        BPL updateLoop
+
+        LDA snakeDirection
+        LSR
+        BCS up
+        LSR
+        BCS right
+        LSR
+        BCS down
+        LSR
+        BCS left
+up:     LDA snakeHeadL
+        SEC
+        SBC #$20
+        STA snakeHeadL
+        BCC upUp
+        RTS
+upUp:   DEC snakeHeadH
+        LDA #$1
+        CMP snakeHeadH
+        BEQ collision
+        RTS
+right:  INC snakeHeadL
+        LDA #$1f
+        BIT snakeHeadL
+        BEQ collision
+        RTS
+down:   LDA snakeHeadL
+        CLC
+        ADC #$20
+        STA snakeHeadL
+        BCS downDown
+        RTS
+downDown:
+        INC snakeHeadH
+        LDA #$6
+        CMP snakeHeadH
+        BEQ collision
+        RTS
+left:   DEC snakeHeadL
+        LDA snakeHeadL
+        AND #$1f
+        CMP #$1f
+        BEQ collision
+        RTS
+collision:
+        JMP gameOver
+
+drawApple:
+        LDY #0
+        LDA sysRandom
+        STA (appleL),Y
+        RTS
+
+drawSnake:
+        LDX snakeLength
+        LDA #0
+        STA (snakeHeadL,X)      ; erase the end of the tail
+        LDX #0
+        LDA #1
+        STA (snakeHeadL,X)      ; paint the head
+        RTS
+
+spinWheels:
+        LDX #0
+spinLoop:
+        NOP
+        NOP
+        DEX
+        BNE spinLoop
+        RTS
+
+gameOver:
+        BRK
+";
+
+/// A xorshift generator behind the $FE port, so a seed replays a game.
+struct Random {
+    state: u32,
+}
+
+impl Random {
+    fn next(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as u8
+    }
+}
+
+impl IoHandler for Random {
+    fn read(&mut self, _address: u16) -> Option<u8> {
+        Some(self.next())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    GameOver,
+}
+
+/// A terminal color for each pixel value, as in the tutorial.
+fn color(pixel: u8) -> (char, u8) {
+    match pixel {
+        0 => ('.', 30),
+        1 => ('#', 97),
+        2 | 9 => ('%', 90),
+        3 | 10 => ('r', 31),
+        4 | 11 => ('g', 32),
+        5 | 12 => ('b', 34),
+        6 | 13 => ('m', 35),
+        7 | 14 => ('y', 33),
+        _ => ('c', 36),
+    }
+}
+
+pub struct Snake {
+    pub cpu: CPU<IoBus>,
+    game_loop: u16,
+    game_over: u16,
+}
+
+impl Snake {
+    /// A fresh game; the same `seed` always plays out the same way.
+    pub fn new(seed: u32) -> Self {
+        let assembly = asm::assemble(CpuVariant::Nmos6502, SOURCE).expect("the snake source assembles");
+        let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, IoBus::default());
+        // xorshift gets stuck at 0
+        cpu.bus.map(RANDOM..=RANDOM, Random { state: seed.max(1) });
+        cpu.load_at(assembly.origin, &assembly.bytes)
+            .expect("the snake program fits in memory");
+        cpu.set_vector(Vector::Reset, assembly.origin);
+        cpu.reset();
+        let mut snake = Snake {
+            cpu,
+            game_loop: assembly.labels["loop"],
+            game_over: assembly.labels["gameOver"],
+        };
+        // set up the snake and the apple, stopping at the top of the game loop
+        snake.frame().expect("the snake program initialises");
+        snake
+    }
+
+    /// Sets the last key pressed, as an ASCII code.
+    pub fn press(&mut self, key: u8) {
+        self.cpu.write_memory(LAST_KEY, key);
+    }
+
+    /// Runs one pass of the game loop: move, then draw.
+    pub fn frame(&mut self) -> Result<Status, CpuError> {
+        if self.cpu.program_counter == self.game_over {
+            return Ok(Status::GameOver);
+        }
+        loop {
+            self.cpu.step()?;
+            match self.cpu.program_counter {
+                pc if pc == self.game_over => return Ok(Status::GameOver),
+                pc if pc == self.game_loop => return Ok(Status::Running),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.cpu.read_memory(SCREEN + (y * WIDTH + x) as u16)
+    }
+
+    /// The screen as text, one character per pixel: `.` for black, `#` for
+    /// white and a letter for each other color.
+    pub fn render_text(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for y in 0..HEIGHT {
+            text.extend((0..WIDTH).map(|x| color(self.pixel(x, y)).0));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// The screen in ANSI colors, two pixel rows per line of upper half
+    /// blocks: the foreground is the top pixel, the background the bottom.
+    pub fn render_ansi(&self) -> String {
+        let mut text = String::new();
+        for y in (0..HEIGHT).step_by(2) {
+            for x in 0..WIDTH {
+                let top = color(self.pixel(x, y)).1;
+                let bottom = color(self.pixel(x, y + 1)).1;
+                write!(text, "\x1b[{};{}m\u{2580}", top, bottom + 10).unwrap();
+            }
+            text.push_str("\x1b[0m\r\n");
+        }
+        text
+    }
+}
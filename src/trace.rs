@@ -0,0 +1,248 @@
+//! Instruction tracing: one line of CPU state per instruction, written before
+//! it executes.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::bus::{Bus, FlatRam};
+use crate::cpu::{AddressingMode, CpuFlags, CpuVariant, CPU};
+use crate::disasm::{self, Instruction};
+use crate::opcodes::{self, OpCode};
+
+/// The layout of a trace line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// nestest.log, without the PPU column:
+    /// `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7`
+    Nestest,
+    /// Mesen's default CPU trace, with the flags spelled out:
+    /// `C000  JMP $C5F5                 A:00 X:00 Y:00 S:FD P:nvUbdIzc CYC:7`
+    Mesen,
+}
+
+fn peek_u16<B: Bus>(cpu: &CPU<B>, address: u16, next: u16) -> u16 {
+    (cpu.read_memory(next) as u16) << 8 | cpu.read_memory(address) as u16
+}
+
+/// Where an indexed or indirect operand ends up, read without side effects.
+fn effective_address<B: Bus>(cpu: &CPU<B>, instruction: &Instruction) -> Option<u16> {
+    let operand = instruction.operand_address()?;
+    let zero_page = operand as u8;
+    let address = match instruction.opcode?.mode {
+        AddressingMode::ZeroPageX => zero_page.wrapping_add(cpu.register_x) as u16,
+        AddressingMode::ZeroPageY => zero_page.wrapping_add(cpu.register_y) as u16,
+        AddressingMode::AbsoluteX => operand.wrapping_add(cpu.register_x as u16),
+        AddressingMode::AbsoluteY => operand.wrapping_add(cpu.register_y as u16),
+        AddressingMode::IndirectX => {
+            let pointer = zero_page.wrapping_add(cpu.register_x);
+            peek_u16(cpu, pointer as u16, pointer.wrapping_add(1) as u16)
+        }
+        AddressingMode::IndirectY => {
+            peek_u16(cpu, operand, zero_page.wrapping_add(1) as u16).wrapping_add(cpu.register_y as u16)
+        }
+        AddressingMode::ZeroPageIndirect => peek_u16(cpu, operand, zero_page.wrapping_add(1) as u16),
+        AddressingMode::Indirect => {
+            // NMOS JMP ($xxFF) takes the high byte from $xx00
+            let next = if cpu.variant == CpuVariant::Cmos65C02 {
+                operand.wrapping_add(1)
+            } else {
+                (operand & 0xff00) | (operand.wrapping_add(1) & 0x00ff)
+            };
+            peek_u16(cpu, operand, next)
+        }
+        _ => return None,
+    };
+    Some(address)
+}
+
+fn is_jump(opcode: &OpCode) -> bool {
+    matches!(opcode.instruction, opcodes::Instruction::JMP | opcodes::Instruction::JSR)
+}
+
+/// What nestest.log shows after the operand: the address it resolves to and
+/// the value there.
+fn nestest_suffix<B: Bus>(cpu: &CPU<B>, instruction: &Instruction) -> String {
+    let (opcode, operand) = match (instruction.opcode, instruction.operand_address()) {
+        (Some(opcode), Some(operand)) => (opcode, operand),
+        _ => return String::new(),
+    };
+    let address = effective_address(cpu, instruction);
+    let value = |address: u16| cpu.read_memory(address);
+
+    match (opcode.mode, address) {
+        (AddressingMode::ZeroPage, _) => format!(" = {:02X}", value(operand)),
+        (AddressingMode::Absolute, _) if is_jump(opcode) => String::new(),
+        (AddressingMode::Absolute, _) => format!(" = {:02X}", value(operand)),
+        (AddressingMode::ZeroPageX | AddressingMode::ZeroPageY, Some(address)) => {
+            format!(" @ {:02X} = {:02X}", address, value(address))
+        }
+        (AddressingMode::AbsoluteX | AddressingMode::AbsoluteY, Some(address)) => {
+            format!(" @ {:04X} = {:02X}", address, value(address))
+        }
+        (AddressingMode::IndirectX, Some(address)) => {
+            let pointer = (operand as u8).wrapping_add(cpu.register_x);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, address, value(address))
+        }
+        (AddressingMode::IndirectY, Some(address)) => {
+            let base = address.wrapping_sub(cpu.register_y as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, address, value(address))
+        }
+        (AddressingMode::ZeroPageIndirect, Some(address)) => {
+            format!(" = {:04X} = {:02X}", address, value(address))
+        }
+        (AddressingMode::Indirect, Some(address)) => format!(" = {:04X}", address),
+        _ => String::new(),
+    }
+}
+
+/// Mesen's version: the resolved address in brackets, then the value there.
+fn mesen_suffix<B: Bus>(cpu: &CPU<B>, instruction: &Instruction) -> String {
+    let (opcode, operand) = match (instruction.opcode, instruction.operand_address()) {
+        (Some(opcode), Some(operand)) => (opcode, operand),
+        _ => return String::new(),
+    };
+
+    let mut suffix = String::new();
+    let address = match opcode.mode {
+        AddressingMode::ZeroPage | AddressingMode::Absolute => operand,
+        _ => match effective_address(cpu, instruction) {
+            Some(address) => {
+                suffix += &format!(" [${:04X}]", address);
+                address
+            }
+            None => return suffix,
+        },
+    };
+    if !is_jump(opcode) {
+        suffix += &format!(" = ${:02X}", cpu.read_memory(address));
+    }
+    suffix
+}
+
+/// P as letters, upper case when set, in the order `NVUBDIZC`.
+fn flag_letters(status: CpuFlags) -> String {
+    "NVUBDIZC"
+        .chars()
+        .enumerate()
+        .map(|(i, letter)| {
+            if status.bits() & (0x80 >> i) != 0 {
+                letter
+            } else {
+                letter.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+/// Formats the instruction at PC and the register state before it runs.
+/// Memory is read with `peek`, so tracing never disturbs the bus.
+pub fn format_line<B: Bus>(cpu: &CPU<B>, format: TraceFormat) -> String {
+    let pc = cpu.program_counter;
+    let bytes: Vec<u8> = (0..3).map(|i| cpu.read_memory(pc.wrapping_add(i))).collect();
+    let instruction = disasm::decode(cpu.variant, &bytes, pc);
+    let unofficial = instruction.opcode.is_some_and(|opcode| opcode.unofficial);
+
+    match format {
+        TraceFormat::Nestest => {
+            let hex: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let asm = format!(
+                "{:04X}  {:8} {}{}{}",
+                pc,
+                hex.join(" "),
+                if unofficial { '*' } else { ' ' },
+                instruction,
+                nestest_suffix(cpu, &instruction)
+            );
+            format!(
+                "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                asm,
+                cpu.register_a,
+                cpu.register_x,
+                cpu.register_y,
+                cpu.status.bits(),
+                cpu.stack_pointer,
+                cpu.cycles
+            )
+        }
+        TraceFormat::Mesen => {
+            let asm = format!("{}{}{}", if unofficial { "*" } else { "" }, instruction, mesen_suffix(cpu, &instruction));
+            format!(
+                "{:04X}  {:25} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{} CYC:{}",
+                pc,
+                asm,
+                cpu.register_a,
+                cpu.register_x,
+                cpu.register_y,
+                cpu.stack_pointer,
+                flag_letters(cpu.status),
+                cpu.cycles
+            )
+        }
+    }
+}
+
+type Trigger<B> = Box<dyn FnMut(&CPU<B>) -> bool>;
+
+/// Writes a trace line to `sink` for each instruction it is shown, optionally
+/// only inside some address ranges or once a trigger condition has fired.
+///
+/// ```ignore
+/// let mut tracer = Tracer::new(io::stdout(), TraceFormat::Nestest).with_range(0xc000..=0xffff);
+/// loop {
+///     tracer.trace(&cpu)?;
+///     cpu.step()?;
+/// }
+/// ```
+pub struct Tracer<W: Write, B: Bus = FlatRam> {
+    sink: W,
+    format: TraceFormat,
+    ranges: Vec<RangeInclusive<u16>>,
+    trigger: Option<Trigger<B>>,
+}
+
+impl<W: Write, B: Bus> Tracer<W, B> {
+    pub fn new(sink: W, format: TraceFormat) -> Self {
+        Tracer {
+            sink,
+            format,
+            ranges: Vec::new(),
+            trigger: None,
+        }
+    }
+
+    /// Only traces instructions whose PC is in `range`. Can be given several times.
+    pub fn with_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Stays silent until `trigger` returns true, then traces from that
+    /// instruction on.
+    pub fn with_trigger<F>(mut self, trigger: F) -> Self
+    where
+        F: FnMut(&CPU<B>) -> bool + 'static,
+    {
+        self.trigger = Some(Box::new(trigger));
+        self
+    }
+
+    /// Traces the instruction at PC, if the filters allow it. Call before `step`.
+    pub fn trace(&mut self, cpu: &CPU<B>) -> io::Result<()> {
+        if let Some(trigger) = &mut self.trigger {
+            if !trigger(cpu) {
+                return Ok(());
+            }
+            self.trigger = None;
+        }
+
+        let pc = cpu.program_counter;
+        if !self.ranges.is_empty() && !self.ranges.iter().any(|range| range.contains(&pc)) {
+            return Ok(());
+        }
+        writeln!(self.sink, "{}", format_line(cpu, self.format))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.sink
+    }
+}
@@ -0,0 +1,165 @@
+use rs_nes::asm::{self, AsmError};
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::disasm;
+
+fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    asm::assemble(CpuVariant::Nmos6502, source).map(|assembly| assembly.bytes)
+}
+
+fn error_at(source: &str) -> (usize, usize, String) {
+    let err = assemble(source).unwrap_err();
+    (err.line, err.column, err.message)
+}
+
+#[test]
+fn test_every_addressing_mode() {
+    let source = "
+        NOP
+        ASL A
+        LDA #$10
+        LDA $10
+        LDA $10,X
+        LDX $10,Y
+        LDA $1234
+        LDA $1234,X
+        LDA $1234,Y
+        LDA ($10,X)
+        LDA ($10),Y
+        JMP ($1234)
+        JSR $1234
+        BNE *
+    ";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0xea, 0x0a, 0xa9, 0x10, 0xa5, 0x10, 0xb5, 0x10, 0xb6, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12, 0xb9,
+            0x34, 0x12, 0xa1, 0x10, 0xb1, 0x10, 0x6c, 0x34, 0x12, 0x20, 0x34, 0x12, 0xd0, 0xfe,
+        ]
+    );
+}
+
+#[test]
+fn test_disassembly_reassembles_to_the_same_bytes() {
+    let source = "LDA #$C0\nTAX\nINX\nSTA $0200,Y\nROR $44,X\nLAX ($20),Y\nBRK";
+    let bytes = assemble(source).unwrap();
+    let listing: Vec<String> = disasm::disassemble(CpuVariant::Nmos6502, &bytes, 0x8000)
+        .iter()
+        .map(|i| i.to_string())
+        .collect();
+    assert_eq!(listing.join("\n"), source);
+}
+
+#[test]
+fn test_labels_and_directives() {
+    let source = r#"
+        .org $0600
+ptr     = $20
+start:  LDX #<message           ; forward references
+        LDY #>message
+        STX ptr
+        STY ptr+1
+loop:   LDA (ptr),Y
+        BEQ done
+        INY
+        BNE loop
+done:   JMP start
+message:
+        .byte "Hi", '!', 0
+        .word start, done - start, *
+    "#;
+    let assembly = asm::assemble(CpuVariant::Nmos6502, source).unwrap();
+    assert_eq!(assembly.origin, 0x0600);
+    assert_eq!(assembly.labels["loop"], 0x0608);
+    assert_eq!(assembly.labels["message"], 0x0612);
+    assert_eq!(
+        assembly.bytes,
+        [
+            0xa2, 0x12, 0xa0, 0x06, 0x86, 0x20, 0x84, 0x21, 0xb1, 0x20, 0xf0, 0x03, 0xc8, 0xd0, 0xf9, 0x4c, 0x00,
+            0x06, b'H', b'i', b'!', 0x00, 0x00, 0x06, 0x0f, 0x00, 0x1a, 0x06,
+        ]
+    );
+    assert_eq!(assembly.symbols()[&0x0608], "loop");
+}
+
+#[test]
+fn test_expressions_and_operand_size() {
+    let source = "
+zero    = $10
+        LDA #(1 + 2) * 3 - %101
+        LDA #'A' | $80
+        LDA #1 << 4 >> 2
+        LDA #-1
+        LDA zero          ; zero page once known
+        LDA later         ; absolute: not known in the first pass
+later   = $10
+        .org $9000
+        .byte <$1234, >$1234, $0f & $3c ^ $ff
+    ";
+    let assembly = asm::assemble(CpuVariant::Nmos6502, source).unwrap();
+    assert_eq!(assembly.origin, 0x8000);
+    assert_eq!(
+        assembly.bytes[..13],
+        [0xa9, 0x04, 0xa9, 0xc1, 0xa9, 0x04, 0xa9, 0xff, 0xa5, 0x10, 0xad, 0x10, 0x00]
+    );
+    // the gap up to .org $9000 is zero-filled
+    assert_eq!(assembly.bytes.len(), 0x1003);
+    assert_eq!(assembly.bytes[0x1000..], [0x34, 0x12, 0xf3]);
+}
+
+#[test]
+fn test_65c02_instructions() {
+    let source = "
+        LDA ($10)
+        JMP ($1234,X)
+        STZ $10
+here:   BBS0 $10,here
+        RMB7 $20
+    ";
+    let assembly = asm::assemble(CpuVariant::Cmos65C02, source).unwrap();
+    assert_eq!(
+        assembly.bytes,
+        [0xb2, 0x10, 0x7c, 0x34, 0x12, 0x64, 0x10, 0x8f, 0x10, 0xfd, 0x77, 0x20]
+    );
+    assert!(asm::assemble(CpuVariant::Nmos6502, "STZ $10").is_err());
+}
+
+#[test]
+fn test_errors_report_line_and_column() {
+    assert_eq!(error_at("NOP\n  FOO #1"), (2, 3, "unknown instruction `FOO`".to_string()));
+    assert_eq!(error_at("  JMP nowhere"), (1, 7, "undefined label `nowhere`".to_string()));
+    assert_eq!(error_at("LDX $10,X"), (1, 5, "`LDX` does not support this addressing mode".to_string()));
+    assert_eq!(error_at("LDA #$100"), (1, 6, "value 256 does not fit in a byte".to_string()));
+    assert_eq!(error_at("LDA ($1234),Y"), (1, 6, "address $1234 is not in zero page".to_string()));
+    assert_eq!(error_at("a: NOP"), (1, 1, "`a` cannot be used as a label".to_string()));
+    assert_eq!(error_at("x1: NOP\nx1: NOP"), (2, 1, "`x1` is already defined".to_string()));
+    assert_eq!(error_at("LDA #1 2"), (1, 8, "expected end of line".to_string()));
+    assert_eq!(error_at(".fill 3"), (1, 1, "unknown directive `.fill`".to_string()));
+    assert_eq!(error_at("LDA #@"), (1, 6, "unexpected character `@`".to_string()));
+    assert_eq!(error_at("LDA #-(1<<63)"), (1, 7, "negation overflows".to_string()));
+    assert_eq!(error_at("LDA #(1<<63)/-1"), (1, 6, "division overflows".to_string()));
+
+    let far = format!("BNE target\n.byte {}\ntarget: NOP", vec!["0"; 200].join(","));
+    assert_eq!(error_at(&far), (1, 5, "branch target is 200 bytes away, out of range".to_string()));
+
+    let err = assemble("TAX\nLDA").unwrap_err();
+    assert_eq!(err.to_string(), "line 2, column 4: `LDA` needs an operand");
+}
+
+#[test]
+fn test_program_loads_into_cpu() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(asm::program("LDA #$C0\nTAX\nINX\nBRK")).unwrap();
+    assert_eq!(cpu.register_x, 0xc1);
+}
+
+#[test]
+#[should_panic(expected = "line 1, column 1: unknown instruction `BOGUS`")]
+fn test_program_panics_on_bad_source() {
+    asm::program("BOGUS");
+}
+
+#[test]
+#[should_panic(expected = "program is assembled at $0600, but CPU::load puts it at $8000")]
+fn test_program_panics_on_another_origin() {
+    asm::program(".org $0600\nNOP");
+}
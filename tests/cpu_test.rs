@@ -1,25 +1,27 @@
-use rs_nes::cpu::CPU;
+use rs_nes::cpu::{interrupt, CpuError, CpuFlags, CpuVariant, CPU};
+use rs_nes::bus::{Bus, FlatRam};
+use rs_nes::opcodes;
 
 #[test]
     fn test_0xa9_lda_immediate_load_data() {
         let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x05, 0x00]);
+        cpu.load_and_run(vec![0xa9, 0x05, 0x00]).unwrap();
         assert_eq!(cpu.register_a, 5);
-        assert!(cpu.status & 0b0000_0010 == 0);
-        assert!(cpu.status & 0b1000_0000 == 0);
+        assert!(!cpu.status.zero());
+        assert!(!cpu.status.negative());
     }
 
     #[test]
     fn test_0xa9_lda_zero_flag() {
         let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x00, 0x00]);
-        assert!(cpu.status & 0b0000_0010 == 0b10);
+        cpu.load_and_run(vec![0xa9, 0x00, 0x00]).unwrap();
+        assert!(cpu.status.zero());
     }
 
     #[test]
     fn test_0xaa_tax_move_a_to_x() {
         let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0x0A,0xaa, 0x00]);
+        cpu.load_and_run(vec![0xa9, 0x0A,0xaa, 0x00]).unwrap();
 
         assert_eq!(cpu.register_x, 10)
     }
@@ -27,7 +29,7 @@ use rs_nes::cpu::CPU;
     #[test]
     fn test_5_ops_working_together() {
         let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]);
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]).unwrap();
 
         assert_eq!(cpu.register_x, 0xc1)
     }
@@ -35,7 +37,7 @@ use rs_nes::cpu::CPU;
     #[test]
     fn test_inx_overflow() {
         let mut cpu = CPU::new();
-        cpu.load_and_run(vec![0xa9, 0xff, 0xaa,0xe8, 0xe8, 0x00]);
+        cpu.load_and_run(vec![0xa9, 0xff, 0xaa,0xe8, 0xe8, 0x00]).unwrap();
 
         assert_eq!(cpu.register_x, 1)
     }
@@ -45,7 +47,856 @@ use rs_nes::cpu::CPU;
         let mut cpu = CPU::new();
         cpu.write_memory(0x10, 0x55);
 
-        cpu.load_and_run(vec![0xa5, 0x10, 0x00]);
+        cpu.load_and_run(vec![0xa5, 0x10, 0x00]).unwrap();
 
         assert_eq!(cpu.register_a, 0x55);
-    }
\ No newline at end of file
+    }
+
+    #[test]
+    fn test_adc_sets_carry_and_overflow() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x50, 0x69, 0x50, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xa0);
+        assert!(cpu.status.overflow());
+        assert!(!cpu.status.carry());
+
+        cpu.load_and_run(vec![0xa9, 0xff, 0x69, 0x02, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(cpu.status.carry());
+        assert!(!cpu.status.overflow());
+    }
+
+    #[test]
+    fn test_sbc_borrows_when_carry_clear() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x05, 0x38, 0xe9, 0x03, 0x18, 0xe9, 0x01, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.zero());
+        assert!(cpu.status.carry());
+    }
+
+    #[test]
+    fn test_shifts_and_rotates_through_carry() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x10, 0x81);
+        cpu.load_and_run(vec![0x06, 0x10, 0x26, 0x10, 0xa9, 0x01, 0x4a, 0x6a, 0x00]).unwrap();
+
+        assert_eq!(cpu.read_memory(0x10), 0x05);
+        assert_eq!(cpu.register_a, 0x80);
+        assert!(!cpu.status.carry());
+    }
+
+    #[test]
+    fn test_compare_and_branch_loop() {
+        let mut cpu = CPU::new();
+        // LDX #0; loop: INX; CPX #5; BNE loop; BRK
+        cpu.load_and_run(vec![0xa2, 0x00, 0xe8, 0xe0, 0x05, 0xd0, 0xfb, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_x, 5);
+        assert!(cpu.status.zero());
+        assert!(cpu.status.carry());
+    }
+
+    #[test]
+    fn test_bit_copies_memory_bits_into_flags() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x20, 0xc0);
+        cpu.load_and_run(vec![0xa9, 0x01, 0x24, 0x20, 0x00]).unwrap();
+
+        assert!(cpu.status.zero());
+        assert!(cpu.status.overflow());
+        assert!(cpu.status.negative());
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_boundary_bug() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x30ff, 0x00);
+        cpu.write_memory(0x3000, 0x90);
+        cpu.write_memory(0x3100, 0x50);
+        cpu.write_memory(0x9000, 0xe8);
+        cpu.write_memory(0x9001, 0x00);
+        cpu.load_and_run(vec![0x6c, 0xff, 0x30]).unwrap();
+
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_store_and_transfer_registers() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa0, 0x07, 0x98, 0xaa, 0xe8, 0x86, 0x40, 0x84, 0x41, 0xc6, 0x41, 0x00]).unwrap();
+
+        assert_eq!(cpu.read_memory(0x40), 0x08);
+        assert_eq!(cpu.read_memory(0x41), 0x06);
+        assert_eq!(cpu.register_a, 0x07);
+    }
+
+    #[test]
+    fn test_stack_pointer_power_on_value() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xba, 0x00]);
+        cpu.reset();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.register_x, 0xfd);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+    }
+
+    #[test]
+    fn test_pha_pla_round_trip() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x42, 0x48, 0xa9, 0x00, 0x68, 0x00]);
+        cpu.reset();
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        assert_eq!(cpu.read_memory(0x01fd), 0x42);
+
+        cpu.run().unwrap();
+        // only the three bytes pushed by the final BRK remain on the stack
+        assert_eq!(cpu.stack_pointer, 0xfa);
+    }
+
+    #[test]
+    fn test_php_pushes_break_and_unused_bits() {
+        let mut cpu = CPU::new();
+        // SEC; PHP; CLC; PLP; PHP; PLA
+        cpu.load_and_run(vec![0x38, 0x08, 0x18, 0x28, 0x08, 0x68, 0x00]).unwrap();
+
+        assert_eq!(
+            CpuFlags::from_bits_truncate(cpu.register_a),
+            CpuFlags::CARRY | CpuFlags::INTERRUPT_DISABLE | CpuFlags::BREAK | CpuFlags::UNUSED
+        );
+        assert!(cpu.status.carry());
+        assert!(!cpu.status.contains(CpuFlags::BREAK));
+    }
+
+    #[test]
+    fn test_jsr_rts_return_address() {
+        let mut cpu = CPU::new();
+        // $8000 JSR $8006; INX; BRK; ... $8006 TSX; STX $50; LDA $01fc; STA $51; LDX #$10; RTS
+        cpu.load_and_run(vec![
+            0x20, 0x06, 0x80, 0xe8, 0x00, 0x00, 0xba, 0x86, 0x50, 0xad, 0xfc, 0x01, 0x85, 0x51,
+            0xa2, 0x10, 0x60,
+        ]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x11);
+        assert_eq!(cpu.read_memory(0x50), 0xfb);
+        assert_eq!(cpu.read_memory(0x51), 0x02);
+    }
+
+    #[test]
+    fn test_brk_pushes_pc_plus_two_and_status() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0xfffe, 0x00);
+        cpu.write_memory(0xffff, 0x90);
+        cpu.load_and_run(vec![0xea, 0x00, 0xff]).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert_eq!(cpu.read_memory(0x01fd), 0x80);
+        assert_eq!(cpu.read_memory(0x01fc), 0x03);
+        assert!(CpuFlags::from_bits_truncate(cpu.read_memory(0x01fb)).contains(CpuFlags::BREAK | CpuFlags::UNUSED));
+        assert!(cpu.status.interrupt_disable());
+    }
+
+    #[test]
+    fn test_nmi_handler_returns_with_rti() {
+        let mut cpu = CPU::new();
+        // NMI handler at $9000: LDY #$42; PLA; STA $20; PHA; RTI
+        for (i, byte) in [0xa0, 0x42, 0x68, 0x85, 0x20, 0x48, 0x40].iter().enumerate() {
+            cpu.write_memory(0x9000 + i as u16, *byte);
+        }
+        cpu.write_memory(0xfffa, 0x00);
+        cpu.write_memory(0xfffb, 0x90);
+        cpu.load(vec![0xa2, 0x01, 0x00]);
+        cpu.reset();
+        cpu.trigger_nmi();
+        cpu.run().unwrap();
+
+        assert_eq!(cpu.register_y, 0x42);
+        assert_eq!(cpu.register_x, 0x01);
+        // the status byte pushed by the NMI has B clear
+        let pushed = CpuFlags::from_bits_truncate(cpu.read_memory(0x20));
+        assert!(!pushed.contains(CpuFlags::BREAK));
+        assert!(pushed.contains(CpuFlags::UNUSED));
+    }
+
+    #[test]
+    fn test_irq_respects_interrupt_disable_and_cli_delay() {
+        let mut cpu = CPU::new();
+        // IRQ handler at $9000: STX $10; BRK
+        cpu.write_memory(0x9000, 0x86);
+        cpu.write_memory(0x9001, 0x10);
+        cpu.write_memory(0x9002, 0x00);
+        cpu.write_memory(0xfffe, 0x00);
+        cpu.write_memory(0xffff, 0x90);
+        cpu.load(vec![0xe8, 0x58, 0xa2, 0x05, 0xe8, 0x00]);
+        cpu.reset();
+        cpu.set_irq_line(interrupt::IRQ_EXTERNAL, true);
+        assert!(cpu.irq_asserted());
+        cpu.run().unwrap();
+
+        // INX ran masked, CLI only took effect after LDX #$05
+        assert_eq!(cpu.read_memory(0x10), 0x05);
+        assert_eq!(cpu.read_memory(0x01fc), 0x04);
+        assert!(!CpuFlags::from_bits_truncate(cpu.read_memory(0x01fb)).contains(CpuFlags::BREAK));
+    }
+
+    #[test]
+    fn test_irq_line_released_by_all_sources() {
+        let mut cpu = CPU::new();
+        cpu.set_irq_line(interrupt::IRQ_APU_FRAME, true);
+        cpu.set_irq_line(interrupt::IRQ_MAPPER, true);
+        cpu.set_irq_line(interrupt::IRQ_APU_FRAME, false);
+        assert!(cpu.irq_asserted());
+        cpu.set_irq_line(interrupt::IRQ_MAPPER, false);
+        assert!(!cpu.irq_asserted());
+    }
+
+    #[test]
+    fn test_cpu_flags_pushed_byte_conversions() {
+        let flags = CpuFlags::CARRY | CpuFlags::NEGATIVE;
+
+        assert_eq!(flags.to_pushed_byte(true), 0b1011_0001);
+        assert_eq!(flags.to_pushed_byte(false), 0b1010_0001);
+        assert_eq!(
+            CpuFlags::from_pushed_byte(0b1111_1111),
+            CpuFlags::all() - CpuFlags::BREAK
+        );
+        assert_eq!(CpuFlags::from_pushed_byte(0), CpuFlags::UNUSED);
+    }
+
+    #[test]
+    fn test_cycles_include_page_cross_penalty_for_reads_only() {
+        let mut cpu = CPU::new();
+        // LDX #$01; LDA $10ff,X; STA $10ff,X
+        cpu.load(vec![0xa2, 0x01, 0xbd, 0xff, 0x10, 0x9d, 0xff, 0x10]);
+        cpu.reset();
+
+        assert_eq!(cpu.cycles, 7);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.cycles, 19);
+    }
+
+    #[test]
+    fn test_cycles_for_indirect_y_page_cross() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x20, 0xff);
+        cpu.write_memory(0x21, 0x02);
+        // LDY #$00; LDA ($20),Y; INY; LDA ($20),Y
+        cpu.load(vec![0xa0, 0x00, 0xb1, 0x20, 0xc8, 0xb1, 0x20]);
+        cpu.reset();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 6);
+    }
+
+    #[test]
+    fn test_cycles_for_branches() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x80fd, 0xd0);
+        cpu.write_memory(0x80fe, 0x10);
+        // $8000: SEC; BCC +0; BCS +0; JMP $80fd; ... $80fd: BNE +$10 (to $810f)
+        cpu.load(vec![0x38, 0x90, 0x00, 0xb0, 0x00, 0x4c, 0xfd, 0x80]);
+        cpu.reset();
+
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        // not taken
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        // taken, same page
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+        // taken, crosses into page $81
+        assert_eq!(cpu.step().unwrap().cycles, 4);
+        assert_eq!(cpu.program_counter, 0x810f);
+    }
+
+    #[test]
+    fn test_cycles_for_serviced_interrupt() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0xfffa, 0x00);
+        cpu.write_memory(0xfffb, 0x90);
+        cpu.write_memory(0x9000, 0xea);
+        cpu.load(vec![0xea]);
+        cpu.reset();
+        cpu.trigger_nmi();
+
+        assert_eq!(cpu.step().unwrap().cycles, 9);
+        assert_eq!(cpu.program_counter, 0x9001);
+    }
+
+    #[test]
+    fn test_unofficial_opcodes_are_marked() {
+        let lax = opcodes::NMOS_OPCODES[0xa7];
+        assert_eq!(lax.mnemonic, "LAX");
+        assert!(lax.unofficial);
+        assert!(!opcodes::NMOS_OPCODES[0xa5].unofficial);
+        assert!(opcodes::NMOS_OPCODES[0xeb].unofficial);
+    }
+
+    #[test]
+    fn test_lax_and_sax() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x10, 0xf3);
+        // LAX $10; LDX #$0f; SAX $11
+        cpu.load_and_run(vec![0xa7, 0x10, 0xa2, 0x0f, 0x87, 0x11, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0xf3);
+        assert_eq!(cpu.read_memory(0x11), 0x03);
+    }
+
+    #[test]
+    fn test_dcp_and_isb() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x10, 0x06);
+        cpu.write_memory(0x11, 0x01);
+        // LDA #$05; DCP $10; SEC; ISB $11
+        cpu.load_and_run(vec![0xa9, 0x05, 0xc7, 0x10, 0x38, 0xe7, 0x11, 0x00]).unwrap();
+
+        assert_eq!(cpu.read_memory(0x10), 0x05);
+        assert_eq!(cpu.read_memory(0x11), 0x02);
+        assert_eq!(cpu.register_a, 0x03);
+        assert!(cpu.status.carry());
+    }
+
+    #[test]
+    fn test_slo_rla_sre_rra() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x10, 0x81);
+        cpu.write_memory(0x11, 0x03);
+        // LDA #$10; SLO $10 (A = $12, C=1); SRE $11 ($11 = $01, A = $13, C=1); RRA $11 ($11 = $80, C=1, A = $94)
+        cpu.load_and_run(vec![0xa9, 0x10, 0x07, 0x10, 0x47, 0x11, 0x67, 0x11, 0x00]).unwrap();
+
+        assert_eq!(cpu.read_memory(0x10), 0x02);
+        assert_eq!(cpu.read_memory(0x11), 0x80);
+        assert_eq!(cpu.register_a, 0x94);
+        assert!(cpu.status.negative());
+    }
+
+    #[test]
+    fn test_immediate_combined_ops() {
+        let mut cpu = CPU::new();
+        // LDA #$ff; ANC #$80
+        cpu.load_and_run(vec![0xa9, 0xff, 0x0b, 0x80, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert!(cpu.status.carry());
+
+        // LDA #$ff; ALR #$03
+        cpu.load_and_run(vec![0xa9, 0xff, 0x4b, 0x03, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x01);
+        assert!(cpu.status.carry());
+
+        // SEC; LDA #$ff; ARR #$c0
+        cpu.load_and_run(vec![0x38, 0xa9, 0xff, 0x6b, 0xc0, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xe0);
+        assert!(cpu.status.carry());
+        assert!(!cpu.status.overflow());
+
+        // LDA #$0f; LDX #$3c; AXS #$02
+        cpu.load_and_run(vec![0xa9, 0x0f, 0xa2, 0x3c, 0xcb, 0x02, 0x00]).unwrap();
+        assert_eq!(cpu.register_x, 0x0a);
+        assert!(cpu.status.carry());
+    }
+
+    #[test]
+    fn test_unofficial_nops_take_their_cycles() {
+        let mut cpu = CPU::new();
+        // LDX #$01; NOP $12ff,X; NOP #$00; NOP
+        cpu.load(vec![0xa2, 0x01, 0x1c, 0xff, 0x12, 0x80, 0x00, 0x1a]);
+        cpu.reset();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+        assert_eq!(cpu.program_counter, 0x8008);
+    }
+
+    #[test]
+    fn test_shx_stores_x_and_high_byte_plus_one() {
+        let mut cpu = CPU::new();
+        // LDX #$ff; LDY #$01; SHX $0210,Y
+        cpu.load_and_run(vec![0xa2, 0xff, 0xa0, 0x01, 0x9e, 0x10, 0x02, 0x00]).unwrap();
+
+        assert_eq!(cpu.read_memory(0x0211), 0x03);
+    }
+
+    #[test]
+    fn test_2a03_ignores_decimal_mode() {
+        let mut cpu = CPU::new();
+        // SED; CLC; LDA #$09; ADC #$01
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x09, 0x69, 0x01, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x0a);
+    }
+
+    #[test]
+    fn test_nmos_decimal_arr() {
+        let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+        // SED; SEC; LDA #$ff; ARR #$ff: $FF, then both nybbles adjusted
+        cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0xff, 0x6b, 0xff, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x55);
+        assert!(cpu.status.carry());
+        assert!(cpu.status.negative());
+        assert!(!cpu.status.zero());
+        assert!(!cpu.status.overflow());
+
+        // SED; CLC; LDA #$44; ARR #$ff: $22, nothing to adjust
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x44, 0x6b, 0xff, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x22);
+        assert!(!cpu.status.carry());
+        assert!(cpu.status.overflow());
+
+        // the 2A03 has no decimal mode
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0xff, 0x6b, 0xff, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0xff);
+        assert!(cpu.status.carry());
+    }
+
+    #[test]
+    fn test_nmos_decimal_adc_and_sbc() {
+        let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+        // SED; CLC; LDA #$58; ADC #$46
+        cpu.load_and_run(vec![0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x04);
+        assert!(cpu.status.carry());
+
+        // SED; SEC; LDA #$12; SBC #$21
+        cpu.load_and_run(vec![0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x91);
+        assert!(!cpu.status.carry());
+    }
+
+    #[test]
+    fn test_decimal_zero_flag_differs_between_nmos_and_cmos() {
+        // SED; CLC; LDA #$99; ADC #$01
+        let program = vec![0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00];
+
+        let mut nmos = CPU::with_variant(CpuVariant::Nmos6502);
+        nmos.load_and_run(program.clone()).unwrap();
+        assert_eq!(nmos.register_a, 0x00);
+        assert!(nmos.status.carry());
+        // NMOS takes Z from the binary sum $9a, and N from the intermediate $a0
+        assert!(!nmos.status.zero());
+        assert!(nmos.status.negative());
+
+        let mut cmos = CPU::with_variant(CpuVariant::Cmos65C02);
+        cmos.load_and_run(program).unwrap();
+        assert_eq!(cmos.register_a, 0x00);
+        assert!(cmos.status.carry());
+        assert!(cmos.status.zero());
+        assert!(!cmos.status.negative());
+    }
+
+    #[test]
+    fn test_65c02_decimal_adc_takes_extra_cycle() {
+        let mut cpu = CPU::with_variant(CpuVariant::Cmos65C02);
+        cpu.load(vec![0xf8, 0x69, 0x01]);
+        cpu.reset();
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+    }
+
+    #[test]
+    fn test_65c02_new_instructions() {
+        let mut cpu = CPU::with_variant(CpuVariant::Cmos65C02);
+        cpu.write_memory(0x10, 0xff);
+        cpu.write_memory(0x11, 0x0f);
+        // LDX #$42; PHX; LDY #$07; PHY; PLX; PLY; STZ $10; LDA #$05; TSB $11; TRB $11; BRA +1; INX; INC A; BRK
+        cpu.load_and_run(vec![
+            0xa2, 0x42, 0xda, 0xa0, 0x07, 0x5a, 0xfa, 0x7a, 0x64, 0x10, 0xa9, 0x05, 0x04, 0x11,
+            0x14, 0x11, 0x80, 0x01, 0xe8, 0x1a, 0x00,
+        ]).unwrap();
+
+        assert_eq!(cpu.register_x, 0x07);
+        assert_eq!(cpu.register_y, 0x42);
+        assert_eq!(cpu.read_memory(0x10), 0x00);
+        assert_eq!(cpu.read_memory(0x11), 0x0a);
+        assert!(!cpu.status.zero());
+        assert_eq!(cpu.register_a, 0x06);
+    }
+
+    #[test]
+    fn test_65c02_zero_page_indirect_and_bit_branches() {
+        let mut cpu = CPU::with_variant(CpuVariant::Cmos65C02);
+        cpu.write_memory(0x20, 0x00);
+        cpu.write_memory(0x21, 0x03);
+        cpu.write_memory(0x0300, 0x99);
+        // LDA ($20); SMB0 $30; BBS0 $30,+2; LDX #$01; RMB7 $31
+        cpu.write_memory(0x31, 0xff);
+        cpu.load_and_run(vec![0xb2, 0x20, 0x87, 0x30, 0x8f, 0x30, 0x02, 0xa2, 0x01, 0x77, 0x31, 0x00]).unwrap();
+
+        assert_eq!(cpu.register_a, 0x99);
+        assert_eq!(cpu.read_memory(0x30), 0x01);
+        assert_eq!(cpu.register_x, 0x00);
+        assert_eq!(cpu.read_memory(0x31), 0x7f);
+    }
+
+    #[test]
+    fn test_65c02_fixes_jmp_indirect_page_bug() {
+        let mut cpu = CPU::with_variant(CpuVariant::Cmos65C02);
+        cpu.write_memory(0x30ff, 0x00);
+        cpu.write_memory(0x3000, 0x90);
+        cpu.write_memory(0x3100, 0x50);
+        cpu.write_memory(0x5000, 0xe8);
+        cpu.write_memory(0x5001, 0x00);
+        cpu.load_and_run(vec![0x6c, 0xff, 0x30]).unwrap();
+
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    #[test]
+    fn test_opcode_tables_per_variant() {
+        let nmos = opcodes::opcode_table(CpuVariant::Nmos6502);
+        let cmos = opcodes::opcode_table(CpuVariant::Cmos65C02);
+
+        assert_eq!(nmos[0xda].mnemonic, "NOP");
+        assert_eq!(cmos[0xda].mnemonic, "PHX");
+        assert_eq!(cmos[0x6c].cycles, 6);
+        assert_eq!(cmos.len(), 256);
+        assert!(cmos.iter().all(|op| op.mnemonic != "LAX"));
+        for table in [nmos, cmos] {
+            assert!(table.iter().enumerate().all(|(code, op)| op.code as usize == code));
+        }
+        assert_eq!(cmos[0x87].instruction, opcodes::Instruction::SMB);
+        assert_eq!(nmos[0x87].access, opcodes::Access::Write);
+    }
+
+    #[test]
+    fn test_step_reports_opcode_operand_and_interrupt() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0xfffa, 0x00);
+        cpu.write_memory(0xfffb, 0x90);
+        cpu.write_memory(0x9000, 0xea);
+        // LDX #$02; LDA $0300,X
+        cpu.load(vec![0xa2, 0x02, 0xbd, 0x00, 0x03]);
+        cpu.reset();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.opcode.mnemonic, "LDX");
+        assert_eq!(step.operand_address, Some(0x8001));
+        assert_eq!(step.interrupt, None);
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.opcode.code, 0xbd);
+        assert_eq!(step.operand_address, Some(0x0302));
+        assert_eq!(step.cycles, 4);
+
+        cpu.trigger_nmi();
+        let step = cpu.step().unwrap();
+        assert_eq!(step.opcode.mnemonic, "NOP");
+        assert_eq!(step.operand_address, None);
+        assert_eq!(step.interrupt, Some(interrupt::InterruptType::NMI));
+        assert_eq!(step.cycles, 9);
+    }
+
+    #[test]
+    fn test_run_until_predicate() {
+        let mut cpu = CPU::new();
+        // loop: INX; JMP loop
+        cpu.load(vec![0xe8, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        let step = cpu.run_until(|cpu, _| cpu.register_x == 10).unwrap();
+        assert_eq!(step.opcode.mnemonic, "INX");
+        assert_eq!(cpu.program_counter, 0x8001);
+    }
+
+    #[test]
+    fn test_run_cycles_does_not_split_instructions() {
+        let mut cpu = CPU::new();
+        // loop: INX; JMP loop
+        cpu.load(vec![0xe8, 0x4c, 0x00, 0x80]);
+        cpu.reset();
+
+        assert_eq!(cpu.run_cycles(10).unwrap(), 10);
+        assert_eq!(cpu.register_x, 2);
+        assert_eq!(cpu.run_cycles(1).unwrap(), 2);
+        assert_eq!(cpu.cycles, 7 + 12);
+    }
+
+    #[test]
+    fn test_jam_halts_the_cpu() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xe8, 0x02, 0xe8]);
+        cpu.reset();
+
+        let error = cpu.run().unwrap_err();
+        assert_eq!(error, CpuError::Jam { pc: 0x8001, opcode: 0x02 });
+        assert_eq!(error.to_string(), "CPU jammed by opcode $02 at $8001");
+        assert_eq!(cpu.register_x, 1);
+
+        assert_eq!(cpu.step().err(), Some(error));
+        assert_eq!(cpu.program_counter, 0x8001);
+    }
+
+    #[test]
+    fn test_jam_opcodes_are_nops_on_65c02() {
+        let mut cpu = CPU::with_variant(CpuVariant::Cmos65C02);
+        cpu.load_and_run(vec![0x02, 0xea, 0x22, 0xea, 0xe8, 0x00]).unwrap();
+        assert_eq!(cpu.register_x, 1);
+    }
+
+    /// RAM plus a status register at $4000 that clears itself once read.
+    struct LatchBus {
+        ram: FlatRam,
+        status: u8,
+    }
+
+    impl Bus for LatchBus {
+        fn read(&mut self, address: u16) -> u8 {
+            match address {
+                0x4000 => std::mem::replace(&mut self.status, 0),
+                _ => self.ram.read(address),
+            }
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            match address {
+                0x4000 => self.status = data,
+                _ => self.ram.write(address, data),
+            }
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            match address {
+                0x4000 => self.status,
+                _ => self.ram.peek(address),
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_bus_read_side_effects() {
+        let bus = LatchBus { ram: FlatRam::new(), status: 0x80 };
+        let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, bus);
+        // LDA $4000; LDX $4000
+        cpu.load_and_run(vec![0xad, 0x00, 0x40, 0xae, 0x00, 0x40, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+        assert_eq!(cpu.register_x, 0x00);
+        assert_eq!(cpu.bus.status, 0);
+    }
+
+    #[test]
+    fn test_flat_ram_covers_last_address() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0xffff, 0x42);
+        // LDA $FFFF
+        cpu.load_and_run(vec![0xad, 0xff, 0xff, 0x00]).unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.read_memory(0xffff), 0x42);
+    }
+
+    #[test]
+    fn test_program_counter_wraps_past_last_address() {
+        let mut cpu = CPU::new();
+        // $FFFD: LDA #$42; $FFFF: NOP
+        cpu.write_memory(0xfffd, 0xa9);
+        cpu.write_memory(0xfffe, 0x42);
+        cpu.write_memory(0xffff, 0xea);
+        cpu.program_counter = 0xfffd;
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0xffff);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+
+        // $FFFD: JSR $0600; $0600: RTS, back to $0000
+        cpu.write_memory(0xfffd, 0x20);
+        cpu.write_memory(0xfffe, 0x00);
+        cpu.write_memory(0xffff, 0x06);
+        cpu.write_memory(0x0600, 0x60);
+        cpu.program_counter = 0xfffd;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0600);
+        assert_eq!(cpu.read_memory(0x01fd), 0xff);
+        assert_eq!(cpu.read_memory(0x01fc), 0xff);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn test_indirect_mode_resolves_jmp_target() {
+        let mut cpu = CPU::new();
+        cpu.write_memory(0x0200, 0x34);
+        cpu.write_memory(0x0201, 0x12);
+        cpu.write_memory(0x02ff, 0x78);
+        cpu.write_memory(0x0300, 0x56);
+        // JMP ($0200)
+        cpu.load(vec![0x6c, 0x00, 0x02]);
+        cpu.reset();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.operand_address, Some(0x1234));
+        assert_eq!(cpu.program_counter, 0x1234);
+
+        // JMP ($02FF) reads its high byte from $0200, not $0300
+        cpu.write_memory(0x1234, 0x6c);
+        cpu.write_memory(0x1235, 0xff);
+        cpu.write_memory(0x1236, 0x02);
+        let step = cpu.step().unwrap();
+        assert_eq!(step.operand_address, Some(0x3478));
+        assert_eq!(cpu.program_counter, 0x3478);
+    }
+
+    #[test]
+    fn test_relative_mode_resolves_branch_target() {
+        let mut cpu = CPU::new();
+        // $8000: BNE -4, taken since Z is clear after reset, into page $7F
+        cpu.load(vec![0xd0, 0xfc]);
+        cpu.reset();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.operand_address, Some(0x7ffe));
+        assert_eq!(cpu.program_counter, 0x7ffe);
+        assert_eq!(step.cycles, 4);
+    }
+
+    #[test]
+    fn test_jumps_that_land_just_past_their_opcode() {
+        // JMP $8001
+        let mut cpu = CPU::new();
+        cpu.load(vec![0x4c, 0x01, 0x80]);
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8001);
+
+        // BNE -1, into its own offset byte
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xd0, 0xff]);
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8001);
+
+        // RTS at $8006 back to $8007
+        let mut cpu = CPU::new();
+        // $8000 LDA #$80; PHA; LDA #$06; PHA; RTS
+        cpu.load(vec![0xa9, 0x80, 0x48, 0xa9, 0x06, 0x48, 0x60]);
+        cpu.reset();
+        for _ in 0..5 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.program_counter, 0x8007);
+    }
+
+    /// RAM that remembers every address read.
+    #[derive(Default)]
+    struct ReadLogBus {
+        ram: FlatRam,
+        reads: Vec<u16>,
+    }
+
+    impl Bus for ReadLogBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.reads.push(address);
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data);
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.ram.peek(address)
+        }
+    }
+
+    #[test]
+    fn test_step_reads_each_operand_byte_once() {
+        let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, ReadLogBus::default());
+        cpu.write_memory(0x10, 0x00);
+        cpu.write_memory(0x11, 0x03);
+        cpu.write_memory(0x0302, 0x42);
+        // LDY #$02; LDA ($10),Y; JSR $9000
+        cpu.load(vec![0xa0, 0x02, 0xb1, 0x10, 0x20, 0x00, 0x90]);
+        cpu.reset();
+        cpu.step().unwrap();
+
+        cpu.bus.reads.clear();
+        let step = cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(step.operand_address, Some(0x0302));
+        assert_eq!(cpu.bus.reads, vec![0x8002, 0x8003, 0x0010, 0x0011, 0x0302]);
+
+        cpu.bus.reads.clear();
+        let step = cpu.step().unwrap();
+        assert_eq!(step.operand_address, Some(0x9000));
+        assert_eq!(cpu.bus.reads, vec![0x8004, 0x8005, 0x8006]);
+    }
+
+    /// RAM with a device that raises an NMI when `trigger` is written.
+    struct NmiOnWriteBus {
+        ram: FlatRam,
+        trigger: u16,
+        nmi: bool,
+    }
+
+    impl Bus for NmiOnWriteBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.nmi |= address == self.trigger;
+            self.ram.write(address, data);
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.ram.peek(address)
+        }
+
+        fn take_nmi(&mut self) -> bool {
+            std::mem::replace(&mut self.nmi, false)
+        }
+    }
+
+    #[test]
+    fn test_nmi_during_brk_or_irq_hijacks_the_vector() {
+        // raised as the status byte is pushed
+        let bus = NmiOnWriteBus { ram: FlatRam::new(), trigger: 0x01fb, nmi: false };
+        let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, bus);
+        cpu.write_memory(0xfffa, 0x00);
+        cpu.write_memory(0xfffb, 0x90);
+        cpu.write_memory(0xfffe, 0x00);
+        cpu.write_memory(0xffff, 0xa0);
+        // BRK; CLI
+        cpu.load(vec![0x00, 0xff, 0x58]);
+        cpu.reset();
+
+        let step = cpu.step().unwrap();
+        assert_eq!(step.opcode.mnemonic, "BRK");
+        assert_eq!(step.interrupt, None);
+        assert_eq!(cpu.program_counter, 0x9000);
+        // the handler can still tell it was a BRK
+        assert!(CpuFlags::from_bits_truncate(cpu.read_memory(0x01fb)).contains(CpuFlags::BREAK));
+
+        // the same for an IRQ
+        cpu.write_memory(0x9000, 0x40);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x8003);
+        cpu.set_irq_line(interrupt::IRQ_EXTERNAL, true);
+        cpu.write_memory(0x8003, 0xea);
+        cpu.write_memory(0x8004, 0xea);
+        // CLI takes effect after one more instruction
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        // step also runs the first instruction of the handler
+        cpu.write_memory(0x9000, 0xea);
+        let step = cpu.step().unwrap();
+        assert_eq!(step.interrupt, Some(interrupt::InterruptType::IRQ));
+        assert_eq!(cpu.program_counter, 0x9001);
+        assert!(!CpuFlags::from_bits_truncate(cpu.read_memory(0x01fb)).contains(CpuFlags::BREAK));
+    }
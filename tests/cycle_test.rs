@@ -0,0 +1,384 @@
+use rs_nes::bus::{Bus, FlatRam};
+use rs_nes::cpu::{CpuError, CpuFlags, CpuVariant, CPU};
+use rs_nes::opcodes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cycle {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+use Cycle::{Read, Write};
+
+/// RAM that records every bus access.
+struct LogBus {
+    ram: FlatRam,
+    log: Vec<Cycle>,
+}
+
+impl LogBus {
+    fn new() -> Self {
+        LogBus { ram: FlatRam::new(), log: Vec::new() }
+    }
+}
+
+impl Bus for LogBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.ram.read(address);
+        self.log.push(Read(address, data));
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.log.push(Write(address, data));
+        self.ram.write(address, data);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.ram.peek(address)
+    }
+}
+
+fn cpu_with_program(program: &[u8]) -> CPU<LogBus> {
+    let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, LogBus::new());
+    cpu.load(program.to_vec());
+    cpu.reset();
+    cpu.bus.log.clear();
+    cpu
+}
+
+/// Ticks one whole instruction, checking each tick makes exactly one access.
+fn tick_instruction(cpu: &mut CPU<LogBus>) -> Vec<Cycle> {
+    cpu.bus.log.clear();
+    loop {
+        let accesses = cpu.bus.log.len();
+        let done = cpu.tick().unwrap();
+        assert_eq!(cpu.bus.log.len(), accesses + 1);
+        if done.is_some() {
+            return std::mem::take(&mut cpu.bus.log);
+        }
+    }
+}
+
+#[test]
+fn test_read_page_cross_does_dummy_read() {
+    // LDA $12F0,X
+    let mut cpu = cpu_with_program(&[0xbd, 0xf0, 0x12]);
+    cpu.register_x = 0x20;
+    cpu.write_memory(0x1310, 0x42);
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8000, 0xbd),
+            Read(0x8001, 0xf0),
+            Read(0x8002, 0x12),
+            Read(0x1210, 0x00),
+            Read(0x1310, 0x42),
+        ]
+    );
+    assert_eq!(cpu.register_a, 0x42);
+    assert!(cpu.at_instruction_boundary());
+}
+
+#[test]
+fn test_read_without_page_cross_skips_fixup() {
+    // LDA $1200,X
+    let mut cpu = cpu_with_program(&[0xbd, 0x00, 0x12]);
+    cpu.register_x = 0x05;
+
+    assert_eq!(tick_instruction(&mut cpu).len(), 4);
+}
+
+#[test]
+fn test_store_indexed_always_does_dummy_read() {
+    // STA $1200,X
+    let mut cpu = cpu_with_program(&[0x9d, 0x00, 0x12]);
+    cpu.register_a = 0x99;
+    cpu.register_x = 0x05;
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8000, 0x9d),
+            Read(0x8001, 0x00),
+            Read(0x8002, 0x12),
+            Read(0x1205, 0x00),
+            Write(0x1205, 0x99),
+        ]
+    );
+}
+
+#[test]
+fn test_read_modify_write_writes_twice() {
+    // INC $10
+    let mut cpu = cpu_with_program(&[0xe6, 0x10]);
+    cpu.write_memory(0x10, 0x7f);
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8000, 0xe6),
+            Read(0x8001, 0x10),
+            Read(0x0010, 0x7f),
+            Write(0x0010, 0x7f),
+            Write(0x0010, 0x80),
+        ]
+    );
+    assert!(cpu.status.negative());
+}
+
+#[test]
+fn test_zero_page_indexed_reads_unindexed_address_first() {
+    // LDA ($FF,X) with X = 1 wraps to the pointer at $00/$01
+    let mut cpu = cpu_with_program(&[0xa1, 0xff]);
+    cpu.register_x = 0x01;
+    cpu.write_memory(0x00, 0x34);
+    cpu.write_memory(0x01, 0x12);
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8000, 0xa1),
+            Read(0x8001, 0xff),
+            Read(0x00ff, 0x00),
+            Read(0x0000, 0x34),
+            Read(0x0001, 0x12),
+            Read(0x1234, 0x00),
+        ]
+    );
+}
+
+#[test]
+fn test_jsr_and_rts_stack_accesses() {
+    // JSR $8010 ... $8010: RTS
+    let mut cpu = cpu_with_program(&[0x20, 0x10, 0x80]);
+    cpu.write_memory(0x8010, 0x60);
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8000, 0x20),
+            Read(0x8001, 0x10),
+            Read(0x01fd, 0x00),
+            Write(0x01fd, 0x80),
+            Write(0x01fc, 0x02),
+            Read(0x8002, 0x80),
+        ]
+    );
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x8010, 0x60),
+            Read(0x8011, 0x00),
+            Read(0x01fb, 0x00),
+            Read(0x01fc, 0x02),
+            Read(0x01fd, 0x80),
+            Read(0x8002, 0x80),
+        ]
+    );
+    assert_eq!(cpu.program_counter, 0x8003);
+}
+
+#[test]
+fn test_taken_branch_across_page_reads_wrong_page() {
+    let mut cpu = cpu_with_program(&[]);
+    // $80F0: BNE +$20
+    cpu.program_counter = 0x80f0;
+    cpu.write_memory(0x80f0, 0xd0);
+    cpu.write_memory(0x80f1, 0x20);
+
+    assert_eq!(
+        tick_instruction(&mut cpu),
+        vec![
+            Read(0x80f0, 0xd0),
+            Read(0x80f1, 0x20),
+            Read(0x80f2, 0x00),
+            Read(0x8012, 0x00),
+        ]
+    );
+    assert_eq!(cpu.program_counter, 0x8112);
+}
+
+#[test]
+fn test_nmi_sequence_and_brk_hijack() {
+    let mut cpu = cpu_with_program(&[0xea, 0x00]);
+    cpu.write_memory(0xfffa, 0x00);
+    cpu.write_memory(0xfffb, 0x90);
+    cpu.write_memory(0xfffe, 0x00);
+    cpu.write_memory(0xffff, 0xa0);
+
+    cpu.trigger_nmi();
+    let log = tick_instruction(&mut cpu);
+    assert_eq!(
+        log[..7],
+        [
+            Read(0x8000, 0xea),
+            Read(0x8000, 0xea),
+            Write(0x01fd, 0x80),
+            Write(0x01fc, 0x00),
+            Write(0x01fb, 0x24),
+            Read(0xfffa, 0x00),
+            Read(0xfffb, 0x90),
+        ]
+    );
+    assert_eq!(log[7], Read(0x9000, 0x00));
+
+    // an NMI raised while BRK pushes its state takes over the vector fetch
+    let mut cpu = cpu_with_program(&[0x00]);
+    cpu.write_memory(0xfffa, 0x00);
+    cpu.write_memory(0xfffb, 0x90);
+    for _ in 0..3 {
+        cpu.tick().unwrap();
+    }
+    cpu.trigger_nmi();
+    let step = loop {
+        if let Some(step) = cpu.tick().unwrap() {
+            break step;
+        }
+    };
+    assert_eq!(step.opcode.mnemonic, "BRK");
+    assert_eq!(step.interrupt, None);
+    assert_eq!(cpu.program_counter, 0x9000);
+    assert_eq!(cpu.read_memory(0x01fb) & 0x10, 0x10);
+
+    assert_eq!(tick_instruction(&mut cpu)[0], Read(0x9000, 0x00));
+}
+
+#[test]
+fn test_handler_starting_with_brk_matches_step() {
+    let machine = || {
+        let mut cpu = cpu_with_program(&[0xea]);
+        cpu.write_memory(0xfffa, 0x00);
+        cpu.write_memory(0xfffb, 0x90);
+        cpu.write_memory(0xfffe, 0x00);
+        cpu.write_memory(0xffff, 0xa0);
+        cpu.write_memory(0x9000, 0x00);
+        cpu.trigger_nmi();
+        cpu
+    };
+    let mut stepped = machine();
+    let mut ticked = machine();
+
+    let expected = stepped.step().unwrap();
+    let actual = loop {
+        if let Some(step) = ticked.tick().unwrap() {
+            break step;
+        }
+    };
+    assert_eq!(actual.opcode.mnemonic, "BRK");
+    assert_eq!(actual.interrupt, expected.interrupt);
+    assert_eq!(actual.cycles, expected.cycles);
+    assert!(ticked.at_instruction_boundary());
+    assert_eq!(ticked.program_counter, 0xa000);
+    assert_eq!(ticked.program_counter, stepped.program_counter);
+    assert_eq!(ticked.stack_pointer, stepped.stack_pointer);
+    // the BRK pushed $9002 and a status byte with B set
+    for address in 0x01f8..=0x01fa {
+        assert_eq!(ticked.read_memory(address), stepped.read_memory(address), "${:04X}", address);
+    }
+    assert_eq!(ticked.read_memory(0x01f8) & 0x10, 0x10);
+}
+
+/// xorshift, so the comparison below is deterministic
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as u8
+    }
+}
+
+/// A CPU about to run `code` at $8200, with pseudo-random registers and memory.
+fn random_cpu(code: u8, seed: u32) -> CPU<LogBus> {
+    let mut rng = Rng(seed);
+    let mut cpu = CPU::with_bus(CpuVariant::Nmos6502, LogBus::new());
+    for address in (0x0100..0x0200).chain(0x1000..0x1400).chain(0x8000..0x8400) {
+        cpu.write_memory(address, rng.next());
+    }
+    // zero page pointers stay inside $1000-$13FF
+    for pointer in 0..0x100u16 {
+        let byte = rng.next();
+        cpu.write_memory(pointer, if pointer & 1 == 1 { 0x10 | byte & 0x03 } else { byte });
+    }
+    cpu.write_memory(0xfffc, 0x00);
+    cpu.write_memory(0xfffd, 0x82);
+    cpu.write_memory(0xfffe, 0x00);
+    cpu.write_memory(0xffff, 0x11);
+    cpu.write_memory(0x8200, code);
+    cpu.reset();
+
+    cpu.register_a = rng.next();
+    cpu.register_x = rng.next();
+    cpu.register_y = rng.next();
+    cpu.status = CpuFlags::from_pushed_byte(rng.next());
+    cpu.bus.log.clear();
+    cpu
+}
+
+/// Every opcode must leave the same state and take the same number of cycles
+/// whether it is run with `step` or `tick`.
+#[test]
+fn test_tick_matches_step_for_every_opcode() {
+    let table = opcodes::opcode_table(CpuVariant::Nmos6502);
+
+    for code in 0..=0xffu8 {
+        if table[code as usize].mnemonic == "JAM" {
+            continue;
+        }
+        for seed in 1..=16u32 {
+            let mut stepped = random_cpu(code, seed.wrapping_mul(0x9e37_79b9));
+            let mut ticked = random_cpu(code, seed.wrapping_mul(0x9e37_79b9));
+
+            let expected = stepped.step().unwrap();
+            let actual = loop {
+                if let Some(step) = ticked.tick().unwrap() {
+                    break step;
+                }
+            };
+
+            let name = format!("{:02X} {}", code, expected.opcode.mnemonic);
+            assert_eq!(actual.cycles, expected.cycles, "{} cycles", name);
+            assert_eq!(ticked.bus.log.len(), expected.cycles as usize, "{} bus accesses", name);
+            assert_eq!(actual.operand_address, expected.operand_address, "{} operand", name);
+            assert_eq!(ticked.cycles, stepped.cycles, "{}", name);
+            assert_eq!(ticked.program_counter, stepped.program_counter, "{} pc", name);
+            assert_eq!(ticked.register_a, stepped.register_a, "{} a", name);
+            assert_eq!(ticked.register_x, stepped.register_x, "{} x", name);
+            assert_eq!(ticked.register_y, stepped.register_y, "{} y", name);
+            assert_eq!(ticked.stack_pointer, stepped.stack_pointer, "{} sp", name);
+            assert_eq!(ticked.status, stepped.status, "{} p", name);
+
+            let written = stepped.bus.log.iter().chain(ticked.bus.log.iter()).filter_map(|cycle| match cycle {
+                Write(address, _) => Some(*address),
+                Read(..) => None,
+            });
+            for address in written.collect::<Vec<_>>() {
+                assert_eq!(
+                    ticked.read_memory(address),
+                    stepped.read_memory(address),
+                    "{} memory at {:04X}",
+                    name,
+                    address
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tick_is_refused_on_65c02() {
+    let mut cpu = CPU::with_bus(CpuVariant::Cmos65C02, LogBus::new());
+    cpu.load(vec![0xea]);
+    cpu.reset();
+    cpu.bus.log.clear();
+
+    let error = cpu.tick().unwrap_err();
+    assert_eq!(error, CpuError::NoCycleStepping { variant: CpuVariant::Cmos65C02 });
+    assert_eq!(error.to_string(), "cycle stepping is not modelled for the Cmos65C02");
+    assert!(cpu.bus.log.is_empty());
+    assert_eq!(cpu.cycles, 7);
+}
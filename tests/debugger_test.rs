@@ -0,0 +1,134 @@
+use rs_nes::asm;
+use rs_nes::cpu::CpuVariant;
+use rs_nes::debugger::{Debugger, Reply};
+
+const PROGRAM: &str = "
+        .org $8000
+start:  LDX #$00
+loop:   JSR bump
+        INX
+        CPX #$03
+        BNE loop
+        STA $0200
+done:   JMP done
+bump:   CLC
+        ADC #$01
+        RTS
+";
+
+fn debugger() -> Debugger {
+    let assembly = asm::assemble(CpuVariant::Nmos6502, PROGRAM).unwrap();
+    let mut debugger = Debugger::new(CpuVariant::Nmos6502);
+    for (i, &byte) in assembly.bytes.iter().enumerate() {
+        debugger.cpu.write_memory(assembly.origin + i as u16, byte);
+    }
+    debugger.cpu.reset();
+    debugger.cpu.program_counter = assembly.origin;
+    debugger.symbols = assembly.symbols();
+    debugger
+}
+
+fn run(debugger: &mut Debugger, line: &str) -> String {
+    match debugger.command(line) {
+        Reply::Text(text) => text,
+        Reply::Quit => panic!("unexpected quit"),
+    }
+}
+
+#[test]
+fn test_breakpoint_and_continue() {
+    let mut debugger = debugger();
+    assert_eq!(run(&mut debugger, "break done"), "breakpoint at $800D");
+    assert_eq!(run(&mut debugger, "b"), "$800D");
+
+    let output = run(&mut debugger, "continue");
+    assert!(output.starts_with("breakpoint at $800D\n800D  JMP $800D"), "{}", output);
+    assert_eq!(debugger.cpu.register_a, 3);
+
+    // with the breakpoint gone, the JMP-to-self is reported as a trap
+    run(&mut debugger, "delete $800D");
+    assert!(run(&mut debugger, "c").starts_with("trapped in a loop at $800D"));
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut debugger = debugger();
+    run(&mut debugger, "watch 0200 w");
+    let output = run(&mut debugger, "c");
+    assert!(output.starts_with("watchpoint: write $0200 <- $03\n800D"), "{}", output);
+
+    run(&mut debugger, "unwatch 0200");
+    run(&mut debugger, "watch $8012 r");
+    run(&mut debugger, "r pc 8000");
+    // the first read of $8012 is fetching ADC's operand inside `bump`
+    let output = run(&mut debugger, "c");
+    assert!(output.starts_with("watchpoint: read $8012 -> $01"), "{}", output);
+}
+
+#[test]
+fn test_step_next_and_finish() {
+    let mut debugger = debugger();
+    run(&mut debugger, "step");
+    assert_eq!(debugger.cpu.program_counter, 0x8002);
+
+    // next runs the whole subroutine
+    run(&mut debugger, "next");
+    assert_eq!(debugger.cpu.program_counter, 0x8005);
+    assert_eq!(debugger.cpu.register_a, 1);
+
+    // step into the next call, then finish back out of it
+    run(&mut debugger, "s 4");
+    assert_eq!(debugger.cpu.program_counter, 0x8010);
+    run(&mut debugger, "finish");
+    assert_eq!(debugger.cpu.program_counter, 0x8005);
+    assert_eq!(debugger.cpu.register_a, 2);
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut debugger = debugger();
+    assert_eq!(run(&mut debugger, "r"), "PC:8000 A:00 X:00 Y:00 SP:FD P:24 CYC:7");
+    run(&mut debugger, "r a $42");
+    run(&mut debugger, "regs p c3");
+    assert_eq!(run(&mut debugger, "r"), "PC:8000 A:42 X:00 Y:00 SP:FD P:E3 CYC:7");
+    assert_eq!(run(&mut debugger, "r a 100"), "error: $100 does not fit in a register");
+
+    assert_eq!(run(&mut debugger, "w 0300 48 69 0"), "wrote 3 bytes at $0300");
+    assert_eq!(run(&mut debugger, "m 0300 4"), format!("0300: {:47}  Hi..", "48 69 00 00"));
+}
+
+#[test]
+fn test_disassembly_around_pc() {
+    let mut debugger = debugger();
+    run(&mut debugger, "s 3");
+    assert_eq!(
+        run(&mut debugger, "dis"),
+        "  800A  8D 00 02  STA $0200
+        done:
+  800D  4C 0D 80  JMP done
+        bump:
+  8010  18        CLC
+> 8011  69 01     ADC #$01
+  8013  60        RTS
+  8014  00        BRK
+  8015  00        BRK
+  8016  00        BRK
+  8017  00        BRK
+  8018  00        BRK"
+    );
+    assert_eq!(
+        run(&mut debugger, "d start 2"),
+        "        start:\n  8000  A2 00     LDX #$00\n        loop:\n  8002  20 10 80  JSR bump"
+    );
+    // counts are hex, like every other number
+    let listing = run(&mut debugger, "d 8000 10");
+    assert_eq!(listing.lines().filter(|line| !line.ends_with(':')).count(), 0x10);
+}
+
+#[test]
+fn test_unknown_command_and_quit() {
+    let mut debugger = debugger();
+    assert_eq!(run(&mut debugger, "frobnicate"), "error: unknown command `frobnicate`, try `help`");
+    assert_eq!(run(&mut debugger, "   "), "");
+    assert_eq!(debugger.command("quit"), Reply::Quit);
+}
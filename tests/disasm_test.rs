@@ -0,0 +1,106 @@
+use rs_nes::bus::{Bus, FlatRam};
+use rs_nes::cpu::CpuVariant;
+use rs_nes::disasm::{self, Symbols};
+
+fn listing(variant: CpuVariant, bytes: &[u8], origin: u16) -> Vec<String> {
+    disasm::disassemble(variant, bytes, origin).iter().map(|i| i.to_string()).collect()
+}
+
+#[test]
+fn test_operand_syntax_for_every_mode() {
+    let code = [
+        0xea, //             NOP
+        0x0a, //             ASL A
+        0xa9, 0x10, //       LDA #$10
+        0xa5, 0x10, //       LDA $10
+        0xb5, 0x10, //       LDA $10,X
+        0xb6, 0x10, //       LDX $10,Y
+        0xad, 0x34, 0x12, // LDA $1234
+        0xbd, 0x34, 0x12, // LDA $1234,X
+        0xb9, 0x34, 0x12, // LDA $1234,Y
+        0xa1, 0x10, //       LDA ($10,X)
+        0xb1, 0x10, //       LDA ($10),Y
+        0x6c, 0x34, 0x12, // JMP ($1234)
+        0x20, 0x34, 0x12, // JSR $1234
+        0xd0, 0xfe, //       BNE *
+    ];
+    assert_eq!(
+        listing(CpuVariant::Nmos6502, &code, 0x8000),
+        [
+            "NOP",
+            "ASL A",
+            "LDA #$10",
+            "LDA $10",
+            "LDA $10,X",
+            "LDX $10,Y",
+            "LDA $1234",
+            "LDA $1234,X",
+            "LDA $1234,Y",
+            "LDA ($10,X)",
+            "LDA ($10),Y",
+            "JMP ($1234)",
+            "JSR $1234",
+            "BNE $801D",
+        ]
+    );
+}
+
+#[test]
+fn test_65c02_operands() {
+    let code = [
+        0xb2, 0x10, //       LDA ($10)
+        0x7c, 0x34, 0x12, // JMP ($1234,X)
+        0x8f, 0x10, 0xfd, // BBS0 $10,$8005
+    ];
+    assert_eq!(
+        listing(CpuVariant::Cmos65C02, &code, 0x8000),
+        ["LDA ($10)", "JMP ($1234,X)", "BBS0 $10,$8005"]
+    );
+}
+
+#[test]
+fn test_unknown_and_truncated_bytes_are_data() {
+    // JAM, then LDA abs with its high byte missing
+    let instructions = disasm::disassemble(CpuVariant::Nmos6502, &[0x02, 0xad, 0x34], 0x8000);
+    let text: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+    assert_eq!(text, [".db $02", ".db $AD", ".db $34"]);
+    assert!(instructions.iter().all(|i| i.opcode.is_none()));
+    assert_eq!(instructions[2].address, 0x8002);
+}
+
+#[test]
+fn test_symbols_replace_addresses() {
+    let mut symbols = Symbols::new();
+    symbols.insert(0x0010, "ptr".to_string());
+    symbols.insert(0x8000, "loop".to_string());
+    symbols.insert(0x2002, "PPUSTATUS".to_string());
+
+    let code = [
+        0xb1, 0x10, //       LDA (ptr),Y
+        0x2c, 0x02, 0x20, // BIT PPUSTATUS
+        0x30, 0xf9, //       BMI loop
+        0x4c, 0x00, 0x90, // JMP $9000
+    ];
+    let text: Vec<String> = disasm::disassemble(CpuVariant::Nmos6502, &code, 0x8000)
+        .iter()
+        .map(|i| i.with_symbols(&symbols).to_string())
+        .collect();
+    assert_eq!(text, ["LDA (ptr),Y", "BIT PPUSTATUS", "BMI loop", "JMP $9000"]);
+}
+
+#[test]
+fn test_disassemble_bus_range() {
+    let mut ram = FlatRam::new();
+    for (i, &byte) in [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00].iter().enumerate() {
+        ram.write(0xc000 + i as u16, byte);
+    }
+
+    let instructions = disasm::disassemble_bus(CpuVariant::Nmos6502, &ram, 0xc000..=0xc005);
+    let text: Vec<String> = instructions.iter().map(|i| format!("{:04X} {}", i.address, i)).collect();
+    assert_eq!(text, ["C000 LDX #$03", "C002 DEX", "C003 BNE $C002", "C005 BRK"]);
+    assert_eq!(instructions[2].operand_address(), Some(0xc002));
+    assert_eq!(instructions[2].bytes, [0xd0, 0xfd]);
+
+    // the range can run right up to the top of memory
+    assert_eq!(disasm::disassemble_bus(CpuVariant::Nmos6502, &ram, 0xfffe..=0xffff).len(), 2);
+}
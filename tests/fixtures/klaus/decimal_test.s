@@ -0,0 +1,185 @@
+; Verify decimal mode behavior
+; Written by Bruce Clark. This code is public domain.
+; http://www.6502.org/tutorials/decimal_mode.html#B
+;
+; The same test Klaus Dormann's 6502_decimal_test is built from, with his
+; zero page layout and the NMOS 6502 predictions for every flag. It ends at
+; `done` with ERROR = 0 if every ADC and SBC result matched, and ERROR = 1,
+; N1, N2 and Y holding the failing case, if one did not.
+
+N1      = $00           ; the two numbers added or subtracted
+N2      = $01
+HA      = $02           ; binary mode results
+HNVZC   = $03
+DA      = $04           ; decimal mode results
+DNVZC   = $05
+AR      = $06           ; predicted decimal mode results
+NF      = $07
+VF      = $08
+ZF      = $09
+CF      = $0a
+ERROR   = $0b
+N1L     = $0c           ; low and high nybbles of N1 and N2
+N1H     = $0d
+N2L     = $0e
+N2H     = $0f           ; and N2H+1
+
+        .org $0200
+test:   LDY #1          ; loop through both values of the carry flag
+        STY ERROR       ; 1 until the test passes
+        LDA #0
+        STA N1
+        STA N2
+loop1:  LDA N2          ; N2L = N2 & $0F
+        AND #$0f
+        STA N2L
+        LDA N2          ; N2H = N2 & $F0
+        AND #$f0
+        STA N2H
+        ORA #$0f        ; N2H+1 = (N2 & $F0) + $0F
+        STA N2H+1
+loop2:  LDA N1          ; N1L = N1 & $0F
+        AND #$0f
+        STA N1L
+        LDA N1          ; N1H = N1 & $F0
+        AND #$f0
+        STA N1H
+        JSR add
+        JSR a6502
+        JSR compare
+        BNE done
+        JSR sub
+        JSR s6502
+        JSR compare
+        BNE done
+        INC N1          ; all 256 values of N1
+        BNE loop2
+        INC N2          ; all 256 values of N2
+        BNE loop1
+        DEY
+        BPL loop1       ; both values of the carry flag
+        LDA #0          ; passed
+        STA ERROR
+done:   JMP done
+
+; The actual decimal mode and binary mode results of N1 + N2, and the
+; predicted accumulator, carry and V flag.
+add:    SED
+        CPY #1          ; set carry if Y = 1, clear carry if Y = 0
+        LDA N1
+        ADC N2
+        STA DA
+        PHP
+        PLA
+        STA DNVZC
+        CLD
+        CPY #1
+        LDA N1
+        ADC N2
+        STA HA
+        PHP
+        PLA
+        STA HNVZC
+        CPY #1
+        LDA N1L
+        ADC N2L
+        CMP #$0a
+        LDX #0
+        BCC a1
+        INX
+        ADC #5          ; add 6 (carry is set)
+        AND #$0f
+        SEC
+a1:     ORA N1H
+; if N1L + N2L <  $0A, then add N2 & $F0
+; if N1L + N2L >= $0A, then add (N2 & $F0) + $0F + 1 (carry is set)
+        ADC N2H,X
+        PHP
+        BCS a2
+        CMP #$a0
+        BCC a3
+a2:     ADC #$5f        ; add $60 (carry is set)
+        SEC
+a3:     STA AR
+        PHP
+        PLA
+        STA CF
+        PLA             ; all of P, for its N and V flags
+        STA VF
+        RTS
+
+; The actual decimal mode and binary mode results of N1 - N2.
+sub:    SED
+        CPY #1
+        LDA N1
+        SBC N2
+        STA DA
+        PHP
+        PLA
+        STA DNVZC
+        CLD
+        CPY #1
+        LDA N1
+        SBC N2
+        STA HA
+        PHP
+        PLA
+        STA HNVZC
+        RTS
+
+; The predicted accumulator of N1 - N2 on the 6502.
+sub1:   CPY #1
+        LDA N1L
+        SBC N2L
+        LDX #0
+        BCS s11
+        INX
+        SBC #5          ; subtract 6 (carry is clear)
+        AND #$0f
+        CLC
+s11:    ORA N1H
+; if N1L - N2L >= 0, then subtract N2 & $F0
+; if N1L - N2L <  0, then subtract (N2 & $F0) + $0F + 1 (carry is clear)
+        SBC N2H,X
+        BCS s12
+        SBC #$5f        ; subtract $60 (carry is clear)
+s12:    STA AR
+        RTS
+
+; Z clear if the decimal mode results differ from the predicted ones.
+compare:
+        LDA DA
+        CMP AR
+        BNE c1
+        LDA DNVZC
+        EOR NF
+        AND #$80        ; N
+        BNE c1
+        LDA DNVZC
+        EOR VF
+        AND #$40        ; V
+        BNE c1
+        LDA DNVZC
+        EOR ZF
+        AND #2          ; Z
+        BNE c1
+        LDA DNVZC
+        EOR CF
+        AND #1          ; C
+c1:     RTS
+
+; The 6502 predictions: N and V from the decimal sum before its high nybble
+; is adjusted, Z from the binary result, and for SBC every flag as in binary.
+a6502:  LDA VF
+        STA NF
+        LDA HNVZC
+        STA ZF
+        RTS
+
+s6502:  JSR sub1
+        LDA HNVZC
+        STA NF
+        STA VF
+        STA ZF
+        STA CF
+        RTS
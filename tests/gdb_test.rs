@@ -0,0 +1,189 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use rs_nes::asm;
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::gdb::{GdbStub, Response};
+
+const PROGRAM: &str = "
+        .org $8000
+start:  LDX #$00
+loop:   INX
+        STX $0200
+        CPX #$03
+        BNE loop
+done:   JMP done
+";
+
+fn cpu() -> CPU {
+    let assembly = asm::assemble(CpuVariant::Nmos6502, PROGRAM).unwrap();
+    let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+    for (i, &byte) in assembly.bytes.iter().enumerate() {
+        cpu.write_memory(assembly.origin + i as u16, byte);
+    }
+    cpu.reset();
+    cpu.program_counter = assembly.origin;
+    cpu
+}
+
+/// A scripted GDB client: sends packets and reads back acks and replies.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send_raw(&mut self, raw: &[u8]) {
+        self.stream.write_all(raw).unwrap();
+    }
+
+    fn reply(&mut self) -> String {
+        while self.read_byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let sum = [self.read_byte(), self.read_byte()];
+        let expected = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        assert_eq!(std::str::from_utf8(&sum).unwrap(), format!("{:02x}", expected));
+        self.send_raw(b"+");
+        String::from_utf8(data).unwrap()
+    }
+
+    fn packet(&mut self, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        self.send_raw(format!("${}#{:02x}", data, sum).as_bytes());
+        assert_eq!(self.read_byte(), b'+', "no ack for {}", data);
+        self.reply()
+    }
+}
+
+/// Starts a stub on a loopback port. The thread hands the CPU back when the
+/// session ends.
+fn connect() -> (Client, thread::JoinHandle<CPU>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(cpu());
+        stub.serve(stream).unwrap();
+        stub.cpu
+    });
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_nodelay(true).unwrap();
+    (Client { stream }, server)
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let (mut client, server) = connect();
+    assert!(client.packet("qSupported:multiprocess+").contains("PacketSize"));
+    assert_eq!(client.packet("?"), "S05");
+    assert_eq!(client.packet("g"), "00000024fd0080");
+
+    assert_eq!(client.packet("P0=42"), "OK");
+    assert_eq!(client.packet("P5=0480"), "OK");
+    assert_eq!(client.packet("p0"), "42");
+    assert_eq!(client.packet("p5"), "0480");
+    assert_eq!(client.packet("p6"), "E01");
+    assert_eq!(client.packet("G010203c3f00080"), "OK");
+    // bit 5 of P always reads as set
+    assert_eq!(client.packet("g"), "010203e3f00080");
+
+    assert_eq!(client.packet("m8000,3"), "a200e8");
+    assert_eq!(client.packet("M0300,2:beef"), "OK");
+    assert_eq!(client.packet("m0300,2"), "beef");
+    assert_eq!(client.packet("M0300,2:be"), "E01");
+
+    assert_eq!(client.packet("vMustReplyEmpty"), "");
+    assert_eq!(client.packet("D"), "OK");
+    let cpu = server.join().unwrap();
+    assert_eq!(cpu.read_memory(0x0300), 0xbe);
+    assert_eq!(cpu.register_a, 0x01);
+}
+
+#[test]
+fn test_breakpoints_step_and_continue() {
+    let (mut client, server) = connect();
+    assert_eq!(client.packet("s"), "S05");
+    assert_eq!(client.packet("p5"), "0280");
+
+    // a software and a hardware breakpoint
+    assert_eq!(client.packet("Z0,8002,1"), "OK");
+    assert_eq!(client.packet("Z1,800a,1"), "OK");
+    assert_eq!(client.packet("c"), "S05");
+    assert_eq!(client.packet("p5"), "0280");
+    assert_eq!(client.packet("p1"), "01");
+
+    assert_eq!(client.packet("z0,8002,1"), "OK");
+    assert_eq!(client.packet("c"), "S05");
+    assert_eq!(client.packet("p5"), "0a80");
+    assert_eq!(client.packet("m0200,1"), "03");
+
+    // watchpoints are not supported
+    assert_eq!(client.packet("Z2,0200,1"), "");
+    client.send_raw(b"$k#6b");
+    assert_eq!(client.read_byte(), b'+');
+    server.join().unwrap();
+}
+
+#[test]
+fn test_interrupt_stops_a_running_target() {
+    let (mut client, server) = connect();
+    assert_eq!(client.packet("QStartNoAckMode"), "OK");
+    // the program ends in a loop that never hits a breakpoint
+    client.send_raw(b"$c#63");
+    client.send_raw(&[0x03]);
+    assert_eq!(client.reply(), "S02");
+    client.send_raw(b"$p5#a5");
+    assert_eq!(client.reply(), "0a80");
+    client.send_raw(b"$D#44");
+    assert_eq!(client.reply(), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_bad_checksum_is_rejected() {
+    let (mut client, server) = connect();
+    client.send_raw(b"$g#00");
+    assert_eq!(client.read_byte(), b'-');
+    assert_eq!(client.packet("g"), "00000024fd0080");
+    drop(client);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_handle_packet_without_a_socket() {
+    let mut stub = GdbStub::new(cpu());
+    assert_eq!(stub.handle_packet("c"), Response::Resume);
+    assert_eq!(stub.handle_packet("k"), Response::Close(None));
+    assert_eq!(stub.handle_packet("s8002"), Response::Reply("S05".to_string()));
+    assert_eq!(stub.cpu.program_counter, 0x8003);
+}
+
+#[test]
+fn test_memory_packets_fit_the_packet_size() {
+    let mut stub = GdbStub::new(cpu());
+    let reply = |text: &str| Response::Reply(text.to_string());
+    assert_eq!(stub.handle_packet("qSupported"), reply("PacketSize=4000;QStartNoAckMode+"));
+
+    match stub.handle_packet("m0,2000") {
+        Response::Reply(hex) => assert_eq!(hex.len(), 0x4000),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(stub.handle_packet("m0,2001"), reply("E01"));
+    assert_eq!(stub.handle_packet("m0,ffffffff"), reply("E01"));
+
+    let data = "ea".repeat(0x2001);
+    assert_eq!(stub.handle_packet(&format!("M0,2001:{}", data)), reply("E01"));
+    assert_eq!(stub.handle_packet(&format!("M0,2000:{}", &data[2..])), reply("OK"));
+}
@@ -0,0 +1,382 @@
+//! Tom Harte's SingleStepTests for the 65x02: thousands of single-instruction
+//! cases per opcode, each with an initial state, a final state and the bus
+//! access made on every cycle.
+//!
+//! The JSON files come from https://github.com/SingleStepTests/65x02. Copy the
+//! contents of `6502/v1/` to `tests/fixtures/harte/6502/` and of
+//! `rockwell65c02/v1/` to `tests/fixtures/harte/rockwell65c02/`. The
+//! repository does not ship them, so the two suites are ignored by default;
+//! run them with `cargo test --release --test harte_test -- --ignored`.
+//!
+//! NMOS cases are run with `CPU::tick` and checked cycle by cycle. The
+//! cycle-stepped core doesn't model the 65C02, so its cases are run with
+//! `CPU::step`, which makes every access an instruction needs, in the same
+//! order, but none of the dummy reads. Its log is compared in order against
+//! the expected cycles with only these reads left out, and the cycle count is
+//! still checked in full:
+//!
+//! - the byte after a one-byte opcode, and BRK's padding byte
+//! - the stack before a pull, and before JSR pushes its return address
+//! - the byte after the return address, as RTS increments it
+//! - the partly indexed address on a page crossing or an indexed store, and
+//!   the re-read of the operand of a read-modify-write
+//! - the extra cycle of decimal ADC and SBC, of JMP indirect and of the
+//!   65C02's longer NOPs
+//! - the next opcode, as a taken branch adds its offset
+//!
+//! Each of these only puts an address on the bus and throws the byte away,
+//! and nothing in these vectors reacts to being read, so leaving them out
+//! changes no state. Writes are never left out.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use rs_nes::bus::Bus;
+use rs_nes::cpu::{CpuError, CpuFlags, CpuVariant, CPU};
+use rs_nes::opcodes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cycle {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+struct Case {
+    name: String,
+    initial: State,
+    expected: State,
+    cycles: Vec<Cycle>,
+}
+
+fn number(value: &Value, field: &str) -> Result<u64, String> {
+    value[field].as_u64().ok_or_else(|| format!("`{}` is missing or not a number", field))
+}
+
+fn parse_state(value: &Value) -> Result<State, String> {
+    let ram = value["ram"]
+        .as_array()
+        .ok_or("`ram` is missing")?
+        .iter()
+        .map(|entry| match (entry[0].as_u64(), entry[1].as_u64()) {
+            (Some(address), Some(data)) => Ok((address as u16, data as u8)),
+            _ => Err(format!("bad ram entry {}", entry)),
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(State {
+        pc: number(value, "pc")? as u16,
+        s: number(value, "s")? as u8,
+        a: number(value, "a")? as u8,
+        x: number(value, "x")? as u8,
+        y: number(value, "y")? as u8,
+        p: number(value, "p")? as u8,
+        ram,
+    })
+}
+
+fn parse_case(value: &Value) -> Result<Case, String> {
+    let cycles = value["cycles"]
+        .as_array()
+        .ok_or("`cycles` is missing")?
+        .iter()
+        .map(|entry| {
+            let (address, data) = match (entry[0].as_u64(), entry[1].as_u64()) {
+                (Some(address), Some(data)) => (address as u16, data as u8),
+                _ => return Err(format!("bad cycle {}", entry)),
+            };
+            match entry[2].as_str() {
+                Some("read") => Ok(Cycle::Read(address, data)),
+                Some("write") => Ok(Cycle::Write(address, data)),
+                _ => Err(format!("bad cycle {}", entry)),
+            }
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Case {
+        name: value["name"].as_str().unwrap_or("?").to_string(),
+        initial: parse_state(&value["initial"])?,
+        expected: parse_state(&value["final"])?,
+        cycles,
+    })
+}
+
+fn parse_cases(json: &str) -> Result<Vec<Case>, String> {
+    let value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    value.as_array().ok_or("expected an array of cases")?.iter().map(parse_case).collect()
+}
+
+/// Just the bytes a case sets up, recording every access.
+struct SparseBus {
+    memory: HashMap<u16, u8>,
+    log: Vec<Cycle>,
+}
+
+impl Bus for SparseBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.peek(address);
+        self.log.push(Cycle::Read(address, data));
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.log.push(Cycle::Write(address, data));
+        self.memory.insert(address, data);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.get(&address).copied().unwrap_or(0)
+    }
+}
+
+/// Checks that `made` is `expected`, in order, with only reads left out.
+fn compare_without_dummy_reads(made: &[Cycle], expected: &[Cycle]) -> Result<(), String> {
+    let mut made = made.iter().peekable();
+    for (index, cycle) in expected.iter().enumerate() {
+        match made.peek() {
+            Some(&next) if next == cycle => {
+                made.next();
+            }
+            _ if matches!(cycle, Cycle::Read(..)) => {}
+            next => {
+                return Err(format!(
+                    "bus differs at cycle {}: expected {:?}, got {:?}",
+                    index + 1,
+                    cycle,
+                    next
+                ))
+            }
+        }
+    }
+    match made.next() {
+        Some(cycle) => Err(format!("bus access {:?} was not expected", cycle)),
+        None => Ok(()),
+    }
+}
+
+/// Runs one case, describing the first difference from the expected state.
+fn run_case(variant: CpuVariant, case: &Case) -> Result<(), String> {
+    let initial = &case.initial;
+    let bus = SparseBus {
+        memory: initial.ram.iter().copied().collect(),
+        log: Vec::new(),
+    };
+    let mut cpu = CPU::with_bus(variant, bus);
+    cpu.program_counter = initial.pc;
+    cpu.stack_pointer = initial.s;
+    cpu.register_a = initial.a;
+    cpu.register_x = initial.x;
+    cpu.register_y = initial.y;
+    cpu.status = CpuFlags::from_pushed_byte(initial.p);
+
+    let fail = |err: CpuError| err.to_string();
+    let cycles = if variant == CpuVariant::Cmos65C02 {
+        let cycles = cpu.step().map_err(fail)?.cycles as usize;
+        compare_without_dummy_reads(&cpu.bus.log, &case.cycles)?;
+        cycles
+    } else {
+        while cpu.tick().map_err(fail)?.is_none() {}
+        if cpu.bus.log != case.cycles {
+            let diverges = cpu.bus.log.iter().zip(&case.cycles).position(|(a, b)| a != b);
+            let at = diverges.unwrap_or_else(|| cpu.bus.log.len().min(case.cycles.len()));
+            return Err(format!(
+                "bus differs at cycle {}: expected {:?}, got {:?}",
+                at + 1,
+                case.cycles.get(at),
+                cpu.bus.log.get(at)
+            ));
+        }
+        cpu.bus.log.len()
+    };
+    if cycles != case.cycles.len() {
+        return Err(format!("took {} cycles, expected {}", cycles, case.cycles.len()));
+    }
+
+    let expected = &case.expected;
+    let registers = [
+        ("PC", expected.pc, cpu.program_counter),
+        ("S", expected.s as u16, cpu.stack_pointer as u16),
+        ("A", expected.a as u16, cpu.register_a as u16),
+        ("X", expected.x as u16, cpu.register_x as u16),
+        ("Y", expected.y as u16, cpu.register_y as u16),
+        // bits 4 and 5 only exist in a pushed copy of P, which RAM covers
+        ("P", (expected.p & 0xcf) as u16, (cpu.status.bits() & 0xcf) as u16),
+    ];
+    for (name, expected, actual) in registers.iter() {
+        if expected != actual {
+            return Err(format!("{}: expected ${:02X}, got ${:02X}", name, expected, actual));
+        }
+    }
+    for &(address, data) in &expected.ram {
+        let actual = cpu.bus.peek(address);
+        if actual != data {
+            return Err(format!("${:04X}: expected ${:02X}, got ${:02X}", address, data, actual));
+        }
+    }
+    Ok(())
+}
+
+/// The results for one opcode's file.
+struct Summary {
+    opcode: u8,
+    passed: usize,
+    failed: usize,
+    /// The CPU jams on this opcode, which the vectors model as it carrying on
+    /// reading the bus. Failures here are expected.
+    jams: bool,
+    first_failure: Option<String>,
+}
+
+impl Summary {
+    fn line(&self, variant: CpuVariant) -> String {
+        let mnemonic = opcodes::opcode_table(variant)[self.opcode as usize].mnemonic;
+        let mut line = format!("{:02x} {:4} {:6} passed {:6} failed", self.opcode, mnemonic, self.passed, self.failed);
+        if self.jams {
+            line.push_str("  (jams)");
+        } else if let Some(failure) = &self.first_failure {
+            line.push_str(&format!("  first: {}", failure));
+        }
+        line
+    }
+}
+
+fn run_cases(variant: CpuVariant, opcode: u8, cases: &[Case]) -> Summary {
+    let mut summary = Summary {
+        opcode,
+        passed: 0,
+        failed: 0,
+        jams: opcodes::opcode_table(variant)[opcode as usize].instruction == opcodes::Instruction::JAM,
+        first_failure: None,
+    };
+    for case in cases {
+        match run_case(variant, case) {
+            Ok(()) => summary.passed += 1,
+            Err(err) => {
+                summary.failed += 1;
+                summary.first_failure.get_or_insert_with(|| format!("{}: {}", case.name, err));
+            }
+        }
+    }
+    summary
+}
+
+/// Runs every `xx.json` in `dir`, printing a line per opcode.
+///
+/// # Panics
+///
+/// If the directory holds none of the files.
+fn run_directory(variant: CpuVariant, dir: &Path) -> Vec<Summary> {
+    let mut summaries = Vec::new();
+    for opcode in 0..=255u8 {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(_) => continue,
+        };
+        let cases = parse_cases(&json).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        let summary = run_cases(variant, opcode, &cases);
+        println!("{}", summary.line(variant));
+        summaries.push(summary);
+    }
+    assert!(!summaries.is_empty(), "{}: no test vectors found", dir.display());
+    summaries
+}
+
+fn check_directory(variant: CpuVariant, name: &str) {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "harte", name].iter().collect();
+    let summaries = run_directory(variant, &dir);
+    let failing: Vec<String> = summaries
+        .iter()
+        .filter(|summary| summary.failed > 0 && !summary.jams)
+        .map(|summary| format!("{:02x}", summary.opcode))
+        .collect();
+    assert!(failing.is_empty(), "{}: opcodes with failures: {}", name, failing.join(" "));
+}
+
+#[test]
+#[ignore = "needs tests/fixtures/harte/6502/*.json"]
+fn test_harte_6502() {
+    check_directory(CpuVariant::Nmos6502, "6502");
+}
+
+#[test]
+#[ignore = "needs tests/fixtures/harte/rockwell65c02/*.json"]
+fn test_harte_65c02() {
+    check_directory(CpuVariant::Cmos65C02, "rockwell65c02");
+}
+
+/// Two hand-written cases in the SingleStepTests format: `LDA #$42` and
+/// `STA $10`.
+const MINI_CASES: &str = r#"[
+    {
+        "name": "a9 42",
+        "initial": {"pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                    "ram": [[4096, 169], [4097, 66]]},
+        "final": {"pc": 4098, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                  "ram": [[4096, 169], [4097, 66]]},
+        "cycles": [[4096, 169, "read"], [4097, 66, "read"]]
+    },
+    {
+        "name": "85 10",
+        "initial": {"pc": 4096, "s": 253, "a": 127, "x": 0, "y": 0, "p": 36,
+                    "ram": [[4096, 133], [4097, 16], [16, 0]]},
+        "final": {"pc": 4098, "s": 253, "a": 127, "x": 0, "y": 0, "p": 36,
+                  "ram": [[4096, 133], [4097, 16], [16, 127]]},
+        "cycles": [[4096, 133, "read"], [4097, 16, "read"], [16, 127, "write"]]
+    }
+]"#;
+
+#[test]
+fn test_mini_cases() {
+    let mut cases = parse_cases(MINI_CASES).unwrap();
+    for variant in [CpuVariant::Nmos6502, CpuVariant::Cmos65C02].iter() {
+        for case in &cases {
+            assert_eq!(run_case(*variant, case), Ok(()), "{}", case.name);
+        }
+    }
+
+    cases[0].expected.a = 0x43;
+    assert_eq!(run_case(CpuVariant::Nmos6502, &cases[0]), Err("A: expected $43, got $42".to_string()));
+    cases[1].cycles[2] = Cycle::Write(0x10, 0x7e);
+    assert_eq!(
+        run_case(CpuVariant::Nmos6502, &cases[1]),
+        Err("bus differs at cycle 3: expected Some(Write(16, 126)), got Some(Write(16, 127))".to_string())
+    );
+    // the 65C02 compares the log in order, skipping only reads
+    assert_eq!(
+        run_case(CpuVariant::Cmos65C02, &cases[1]),
+        Err("bus differs at cycle 3: expected Write(16, 126), got Some(Write(16, 127))".to_string())
+    );
+    cases[1].cycles[2] = Cycle::Write(0x10, 0x7f);
+    cases[1].cycles.swap(0, 1);
+    assert_eq!(
+        run_case(CpuVariant::Cmos65C02, &cases[1]),
+        Err("bus differs at cycle 3: expected Write(16, 127), got Some(Read(4097, 16))".to_string())
+    );
+    cases[1].cycles.swap(0, 1);
+    let write = cases[1].cycles.pop().unwrap();
+    assert_eq!(
+        run_case(CpuVariant::Cmos65C02, &cases[1]),
+        Err("bus access Write(16, 127) was not expected".to_string())
+    );
+    // a dummy read `step` leaves out only shows in the cycle count
+    cases[1].cycles.extend_from_slice(&[Cycle::Read(0x10, 0x00), write]);
+    assert_eq!(run_case(CpuVariant::Cmos65C02, &cases[1]), Err("took 3 cycles, expected 4".to_string()));
+
+    let summary = run_cases(CpuVariant::Nmos6502, 0xa9, &cases[..1]);
+    assert_eq!(
+        summary.line(CpuVariant::Nmos6502),
+        "a9 LDA       0 passed      1 failed  first: a9 42: A: expected $43, got $42"
+    );
+    assert!(parse_cases(r#"[{"name": "x"}]"#).is_err());
+}
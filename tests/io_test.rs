@@ -0,0 +1,109 @@
+use rs_nes::asm;
+use rs_nes::bus::{Bus, FlatRam, IoBus, IoHandler};
+use rs_nes::cpu::{CpuVariant, CPU};
+
+/// Collects bytes written to its port.
+#[derive(Default)]
+struct Terminal {
+    output: Vec<u8>,
+}
+
+impl IoHandler for Terminal {
+    fn write(&mut self, _address: u16, data: u8) {
+        self.output.push(data);
+    }
+}
+
+/// Counts up by one on every read.
+#[derive(Default)]
+struct Counter {
+    next: u8,
+}
+
+impl IoHandler for Counter {
+    fn read(&mut self, _address: u16) -> Option<u8> {
+        self.next = self.next.wrapping_add(1);
+        Some(self.next)
+    }
+
+    fn peek(&self, _address: u16) -> Option<u8> {
+        Some(self.next.wrapping_add(1))
+    }
+}
+
+/// Answers reads with a fixed byte, except where it is told to pass.
+struct Fixed {
+    data: u8,
+    pass: Option<u16>,
+}
+
+impl IoHandler for Fixed {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        if self.pass == Some(address) {
+            None
+        } else {
+            Some(self.data)
+        }
+    }
+}
+
+fn cpu() -> CPU<IoBus> {
+    CPU::with_bus(CpuVariant::Nmos6502, IoBus::default())
+}
+
+#[test]
+fn test_handlers_see_cpu_accesses() {
+    let mut cpu = cpu();
+    let terminal = cpu.bus.map(0xf001..=0xf001, Terminal::default());
+    let counter = cpu.bus.map(0xf004..=0xf004, Counter::default());
+    cpu.load_and_run(asm::program(
+        "
+        LDX #0
+loop:   LDA message,X
+        BEQ done
+        STA $F001
+        INX
+        BNE loop
+done:   LDA $F004
+        LDA $F004
+        STA $10
+        BRK
+message:
+        .byte \"Hi!\", 0
+    ",
+    ))
+    .unwrap();
+
+    assert_eq!(cpu.bus.handler::<Terminal>(terminal).unwrap().output, b"Hi!");
+    assert_eq!(cpu.read_memory(0x10), 2);
+    // peeking does not advance the counter
+    assert_eq!(cpu.read_memory(0xf004), 3);
+    assert_eq!(cpu.read_memory(0xf004), 3);
+    assert_eq!(cpu.bus.handler::<Counter>(counter).unwrap().next, 2);
+    // writes also reach memory underneath
+    assert_eq!(cpu.read_memory(0xf001), b'!');
+}
+
+#[test]
+fn test_handlers_are_checked_in_mapping_order() {
+    let mut bus = IoBus::new(FlatRam::new());
+    bus.inner.write(0x4001, 0x99);
+    let first = bus.map(0x4000..=0x40ff, Fixed { data: 1, pass: Some(0x4000) });
+    bus.map(0x4000..=0x4000, Fixed { data: 2, pass: None });
+
+    assert_eq!(bus.read(0x4010), 1);
+    // the first handler passes on $4000, so the second one answers
+    assert_eq!(bus.read(0x4000), 2);
+    // outside every range, reads go to memory
+    assert_eq!(bus.read(0x5000), 0);
+
+    bus.handler_mut::<Fixed>(first).unwrap().pass = Some(0x4001);
+    assert_eq!(bus.read(0x4000), 1);
+    assert_eq!(bus.read(0x4001), 0x99);
+
+    assert!(bus.handler::<Counter>(first).is_none());
+    assert!(bus.unmap(first).is_some());
+    assert!(bus.unmap(first).is_none());
+    assert_eq!(bus.read(0x4010), 0);
+    assert_eq!(bus.read(0x4000), 2);
+}
@@ -0,0 +1,152 @@
+//! Klaus Dormann's 6502 functional and decimal tests.
+//!
+//! The decimal test is Bruce Clark's, kept as source in
+//! `tests/fixtures/klaus/decimal_test.s` and checked on every run.
+//!
+//! The functional test is the default build of `6502_functional_test.bin`
+//! from https://github.com/Klaus2m5/6502_65C02_functional_tests. The
+//! repository does not ship it yet, so that test is ignored by default; with
+//! the binary in `tests/fixtures/klaus/`, run it with
+//! `cargo test --release --test klaus_test -- --ignored`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use rs_nes::asm;
+use rs_nes::cpu::{CpuVariant, CPU};
+
+/// Gives up on a test that neither traps nor finishes within this many
+/// instructions. The functional test needs about 30 million.
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+/// Where a Klaus-style test keeps the number of the test case it is running.
+const TEST_CASE: u16 = 0x0200;
+
+struct Suite {
+    /// File name under `tests/fixtures/klaus/`.
+    file: &'static str,
+    /// Where the image is loaded.
+    origin: u16,
+    /// Where execution starts.
+    start: u16,
+    /// The address reached once every test case has passed.
+    done: u16,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Passed,
+    /// The program jumped or branched to itself somewhere other than `done`.
+    Trapped { pc: u16, test_case: u8 },
+    TimedOut { pc: u16, test_case: u8 },
+}
+
+/// Loads `image` at `suite.origin` and runs it from `suite.start` until it
+/// reaches `suite.done` or gets stuck in a loop.
+fn run_suite(suite: &Suite, image: &[u8]) -> (Outcome, CPU) {
+    let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+    for (i, &byte) in image.iter().enumerate() {
+        cpu.write_memory(suite.origin.wrapping_add(i as u16), byte);
+    }
+    cpu.reset();
+    cpu.program_counter = suite.start;
+
+    for _ in 0..MAX_INSTRUCTIONS {
+        let pc = cpu.program_counter;
+        if pc == suite.done {
+            return (Outcome::Passed, cpu);
+        }
+        if let Err(err) = cpu.step() {
+            panic!("{}: {} in test case {:02X}", suite.file, err, cpu.read_memory(TEST_CASE));
+        }
+        if cpu.program_counter == pc {
+            let test_case = cpu.read_memory(TEST_CASE);
+            return (Outcome::Trapped { pc, test_case }, cpu);
+        }
+    }
+    let pc = cpu.program_counter;
+    let test_case = cpu.read_memory(TEST_CASE);
+    (Outcome::TimedOut { pc, test_case }, cpu)
+}
+
+fn load_fixture(file: &str) -> Vec<u8> {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "klaus", file].iter().collect();
+    fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+#[ignore = "needs tests/fixtures/klaus/6502_functional_test.bin"]
+fn test_klaus_functional() {
+    let suite = Suite {
+        file: "6502_functional_test.bin",
+        origin: 0x0000,
+        start: 0x0400,
+        done: 0x3469,
+    };
+    let image = load_fixture(suite.file);
+
+    let (outcome, _) = run_suite(&suite, &image);
+    assert_eq!(outcome, Outcome::Passed);
+}
+
+/// Bruce Clark's decimal mode test, which the decimal build is made from,
+/// kept as source and assembled here so it always runs.
+#[test]
+fn test_klaus_decimal() {
+    let source = include_str!("fixtures/klaus/decimal_test.s");
+    let assembly = asm::assemble(CpuVariant::Nmos6502, source).expect("the decimal test assembles");
+    let suite = Suite {
+        file: "decimal_test.s",
+        origin: assembly.origin,
+        start: assembly.labels["test"],
+        done: assembly.labels["done"],
+    };
+
+    let (outcome, cpu) = run_suite(&suite, &assembly.bytes);
+    assert_eq!(outcome, Outcome::Passed);
+    // ERROR is cleared only when every add and subtract matched
+    assert_eq!(
+        cpu.read_memory(assembly.labels["ERROR"]),
+        0,
+        "decimal test failed for N1 = ${:02X}, N2 = ${:02X}, carry {}",
+        cpu.read_memory(assembly.labels["N1"]),
+        cpu.read_memory(assembly.labels["N2"]),
+        cpu.register_y
+    );
+}
+
+/// A two-case program in the same shape as the functional test: each case
+/// stores its number at $0200 and branches to itself on failure.
+fn mini_suite(second_case_expects: u8) -> Vec<u8> {
+    vec![
+        0xa9, 0x01, //       LDA #$01
+        0x8d, 0x00, 0x02, // STA $0200
+        0xa2, 0x03, //       LDX #$03
+        0xca, //             DEX
+        0xd0, 0xfd, //       BNE $0407
+        0xd0, 0xfe, //       BNE *          ; case 1 fails if X != 0
+        0xa9, 0x02, //       LDA #$02
+        0x8d, 0x00, 0x02, // STA $0200
+        0xa9, 0x05, //       LDA #$05
+        0x69, 0x01, //       ADC #$01
+        0xc9, second_case_expects, // CMP #n
+        0xd0, 0xfe, //       BNE *          ; case 2 fails if A != n
+        0x4c, 0x19, 0x04, // JMP *          ; success
+    ]
+}
+
+#[test]
+fn test_harness_reports_success_and_failing_case() {
+    let suite = Suite {
+        file: "mini",
+        origin: 0x0400,
+        start: 0x0400,
+        done: 0x0419,
+    };
+
+    let (outcome, _) = run_suite(&suite, &mini_suite(0x06));
+    assert_eq!(outcome, Outcome::Passed);
+
+    let (outcome, _) = run_suite(&suite, &mini_suite(0x07));
+    assert_eq!(outcome, Outcome::Trapped { pc: 0x0417, test_case: 0x02 });
+}
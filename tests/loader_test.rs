@@ -0,0 +1,133 @@
+use rs_nes::cpu::{CpuVariant, Vector, CPU};
+use rs_nes::loader::{Image, LoadError, Segment};
+
+/// `LDA #$2A / STA $10 / BRK` at $0600 and three data bytes at $0700.
+const INTEL_HEX: &str = "\
+:020000040000FA
+:03060000A92A859F
+:020603001000E5
+:03070000010203F0
+:0400000500000600F1
+:00000001FF
+";
+
+const SRECORD: &str = "\
+S00600004844521B
+S1080600A92A85100089
+S207000700010203EB
+S5030002FA
+S9030600F6
+";
+
+fn expected_image() -> Image {
+    Image {
+        segments: vec![
+            Segment {
+                origin: 0x0600,
+                bytes: vec![0xa9, 0x2a, 0x85, 0x10, 0x00],
+            },
+            Segment {
+                origin: 0x0700,
+                bytes: vec![1, 2, 3],
+            },
+        ],
+        start: Some(0x0600),
+    }
+}
+
+fn syntax_error(line: usize, message: &str) -> LoadError {
+    LoadError::Syntax {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn test_load_at_checks_bounds() {
+    let mut cpu = CPU::new();
+    cpu.load_at(0xfffe, &[1, 2]).unwrap();
+    assert_eq!(cpu.read_memory(0xffff), 2);
+
+    let err = cpu.load_at(0xfffe, &[3, 4, 5]).unwrap_err();
+    assert_eq!(err, LoadError::DoesNotFit { origin: 0xfffe, len: 3 });
+    assert_eq!(err.to_string(), "3 bytes at $FFFE do not fit below $10000");
+    // nothing was written
+    assert_eq!(cpu.read_memory(0xfffe), 1);
+}
+
+#[test]
+#[should_panic(expected = "32769 bytes at $8000 do not fit below $10000")]
+fn test_load_panics_when_the_program_is_too_big() {
+    CPU::new().load(vec![0xea; 0x8001]);
+}
+
+#[test]
+fn test_vectors() {
+    let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+    cpu.set_vector(Vector::Nmi, 0x1234);
+    cpu.set_vector(Vector::Reset, 0x0600);
+    cpu.set_vector(Vector::Irq, 0xabcd);
+    assert_eq!(cpu.read_memory(0xfffa), 0x34);
+    assert_eq!(cpu.vector(Vector::Irq), 0xabcd);
+
+    cpu.reset();
+    assert_eq!(cpu.program_counter, 0x0600);
+    // BRK goes through the IRQ vector
+    cpu.load_at(0x0600, &[0x00]).unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0xabcd);
+}
+
+#[test]
+fn test_intel_hex_and_srecord() {
+    assert_eq!(Image::from_intel_hex(INTEL_HEX), Ok(expected_image()));
+    assert_eq!(Image::from_srecord(SRECORD), Ok(expected_image()));
+
+    let image = Image::from_intel_hex(INTEL_HEX).unwrap();
+    let mut cpu = CPU::new();
+    cpu.load_image(&image).unwrap();
+    cpu.set_vector(Vector::Reset, image.entry().unwrap());
+    cpu.reset();
+    cpu.run().unwrap();
+    assert_eq!(cpu.read_memory(0x10), 0x2a);
+    assert_eq!(cpu.read_memory(0x0702), 3);
+}
+
+#[test]
+fn test_raw_image() {
+    let image = Image::raw(0xc000, vec![0xea, 0xea]).unwrap();
+    assert_eq!(image.start, None);
+    assert_eq!(image.entry(), Some(0xc000));
+    assert!(Image::raw(0xffff, vec![0xea, 0xea]).is_err());
+
+    // a ROM image that covers the vectors starts at its reset vector
+    let mut rom = vec![0xea; 0x2000];
+    rom[0x1ffc..0x1ffe].copy_from_slice(&[0x34, 0xf2]);
+    assert_eq!(Image::raw(0xe000, rom).unwrap().entry(), Some(0xf234));
+}
+
+#[test]
+fn test_malformed_records() {
+    assert_eq!(Image::from_intel_hex(":03060000A92A8590"), Err(syntax_error(1, "bad checksum")));
+    assert_eq!(Image::from_intel_hex("\n03060000A92A859F"), Err(syntax_error(2, "expected `:`")));
+    assert_eq!(
+        Image::from_intel_hex(":0406000A92A859F"),
+        Err(syntax_error(1, "expected pairs of hex digits"))
+    );
+    assert_eq!(
+        Image::from_intel_hex(":04060000A92A859F"),
+        Err(syntax_error(1, "record length does not match its byte count"))
+    );
+    // data above $FFFF, after an extended linear address of $0001
+    assert_eq!(
+        Image::from_intel_hex(":020000040001F9\n:03060000A92A859F"),
+        Err(LoadError::DoesNotFit { origin: 0x10600, len: 3 })
+    );
+
+    assert_eq!(Image::from_srecord("S1080600A92A85100088"), Err(syntax_error(1, "bad checksum")));
+    assert_eq!(Image::from_srecord("S4030002FA"), Err(syntax_error(1, "unknown record type S4")));
+    assert_eq!(
+        Image::from_srecord("S804010000FA"),
+        Err(syntax_error(1, "start address $10000 is past $FFFF"))
+    );
+}
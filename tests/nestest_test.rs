@@ -0,0 +1,120 @@
+//! Runs kevtris' nestest.nes in automation mode and compares every
+//! instruction against the reference nestest.log.
+//!
+//! The full comparison needs `nestest.nes` and `nestest.log` in
+//! `tests/fixtures/nestest/`, which the repository does not ship, so it is
+//! ignored by default. Run it with
+//! `cargo test --test nestest_test -- --ignored`. The opening of the log is
+//! committed as `nestest_start.log`, and every test run checks our trace
+//! against it, running the code rebuilt from the log's own opcode bytes.
+
+use std::fs;
+use std::path::PathBuf;
+
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::trace::{self, TraceFormat};
+
+/// Lines of the reference log shown before the first mismatch.
+const CONTEXT: usize = 5;
+
+/// Drops the `PPU:` column from a nestest.log line.
+fn without_ppu(line: &str) -> String {
+    match (line.find(" PPU:"), line.find(" CYC:")) {
+        (Some(ppu), Some(cyc)) => format!("{}{}", &line[..ppu], &line[cyc..]),
+        _ => line.to_string(),
+    }
+}
+
+/// A CPU in nestest's automation mode: the PRG ROM of an NROM cartridge
+/// mapped at $8000 and $C000, with execution starting at $C000.
+fn nestest_cpu(rom: &[u8]) -> CPU {
+    assert_eq!(&rom[..4], b"NES\x1a", "not an iNES file");
+    let prg_size = rom[4] as usize * 0x4000;
+    let trainer = if rom[6] & 0x04 != 0 { 512 } else { 0 };
+    let prg = &rom[16 + trainer..16 + trainer + prg_size];
+
+    let mut cpu = CPU::with_variant(CpuVariant::Ricoh2A03);
+    for address in 0x8000..=0xffffu16 {
+        let offset = (address as usize - 0x8000) % prg.len();
+        cpu.write_memory(address, prg[offset]);
+    }
+    cpu.reset();
+    cpu.program_counter = 0xc000;
+    cpu
+}
+
+/// Steps through `log`, failing at the first line our trace disagrees with.
+fn compare_with_log(cpu: &mut CPU, log: &str) -> Result<(), String> {
+    let expected: Vec<String> = log.lines().map(without_ppu).collect();
+
+    for (n, line) in expected.iter().enumerate() {
+        let actual = trace::format_line(cpu, TraceFormat::Nestest);
+        if actual != *line {
+            let mut message = format!("trace diverges from nestest.log at line {}:\n", n + 1);
+            for previous in &expected[n.saturating_sub(CONTEXT)..n] {
+                message += &format!("           {}\n", previous);
+            }
+            message += &format!("expected:  {}\nactual:    {}", line, actual);
+            return Err(message);
+        }
+        if let Err(err) = cpu.step() {
+            return Err(format!("line {}: {}", n + 1, err));
+        }
+    }
+    Ok(())
+}
+
+fn load_fixture(file: &str) -> Vec<u8> {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", "nestest", file].iter().collect();
+    fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+#[test]
+#[ignore = "needs tests/fixtures/nestest/nestest.nes and nestest.log"]
+fn test_nestest_matches_golden_log() {
+    let rom = load_fixture("nestest.nes");
+    let log = load_fixture("nestest.log");
+
+    let mut cpu = nestest_cpu(&rom);
+    if let Err(message) = compare_with_log(&mut cpu, &String::from_utf8_lossy(&log)) {
+        panic!("{}", message);
+    }
+    // nestest leaves its official and unofficial error codes in $02 and $03
+    assert_eq!(cpu.read_memory(0x0002), 0);
+    assert_eq!(cpu.read_memory(0x0003), 0);
+}
+
+/// The opening of nestest.log, through its first branch tests.
+const LOG_START: &str = include_str!("fixtures/nestest/nestest_start.log");
+
+/// An NROM image holding the code `log` runs through, taken from the
+/// address and opcode byte columns of each line.
+fn rom_from_log(log: &str) -> Vec<u8> {
+    let mut rom = vec![0; 16 + 0x4000];
+    rom[..6].copy_from_slice(b"NES\x1a\x01\x01");
+    for line in log.lines() {
+        let address = u16::from_str_radix(&line[..4], 16).unwrap();
+        let offset = 16 + (address - 0xc000) as usize;
+        for (i, byte) in line[6..14].split_whitespace().enumerate() {
+            rom[offset + i] = u8::from_str_radix(byte, 16).unwrap();
+        }
+    }
+    rom
+}
+
+#[test]
+fn test_trace_matches_start_of_log() {
+    let mut cpu = nestest_cpu(&rom_from_log(LOG_START));
+    assert_eq!(compare_with_log(&mut cpu, LOG_START), Ok(()));
+}
+
+#[test]
+fn test_mismatch_reports_first_divergent_line() {
+    let log = LOG_START.replace("STX $10 = 00", "STX $10 = 01");
+    let mut cpu = nestest_cpu(&rom_from_log(LOG_START));
+
+    let message = compare_with_log(&mut cpu, &log).unwrap_err();
+    assert!(message.starts_with("trace diverges from nestest.log at line 4:"), "{}", message);
+    assert!(message.contains("expected:  C5F9  86 10     STX $10 = 01"), "{}", message);
+    assert!(message.contains("actual:    C5F9  86 10     STX $10 = 00"), "{}", message);
+}
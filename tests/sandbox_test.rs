@@ -0,0 +1,83 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rs_nes::asm;
+use rs_nes::cpu::CpuVariant;
+use rs_nes::loader::Image;
+use rs_nes::sandbox::Sandbox;
+
+/// Output that the test can read back after the sandbox has taken it.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Upper-cases its input until a newline or the end of input, then exits
+/// with the number of characters it read.
+const SHOUT: &str = "
+        .org $0600
+        LDX #0
+loop:   LDA $F004
+        BEQ done
+        CMP #10
+        BEQ done
+        INX
+        CMP #'a'
+        BCC put
+        CMP #'z'+1
+        BCS put
+        AND #$DF
+put:    STA $F001
+        JMP loop
+done:   STX $F00F
+        JMP done
+";
+
+fn machine(source: &str, input: &'static [u8]) -> (Sandbox, Output) {
+    let assembly = asm::assemble(CpuVariant::Nmos6502, source).unwrap();
+    let output = Output::default();
+    let mut sandbox = Sandbox::new(CpuVariant::Nmos6502, input, output.clone());
+    sandbox.load(&Image::raw(assembly.origin, assembly.bytes).unwrap()).unwrap();
+    (sandbox, output)
+}
+
+#[test]
+fn test_console_and_exit_ports() {
+    let (mut sandbox, output) = machine(SHOUT, b"Hello, 6502!\nignored");
+    assert_eq!(sandbox.run(), Ok(12));
+    assert_eq!(&output.0.borrow()[..], b"HELLO, 6502!");
+
+    // getchar reads 0 once the input runs out
+    let (mut sandbox, output) = machine(SHOUT, b"abc");
+    assert_eq!(sandbox.run(), Ok(3));
+    assert_eq!(&output.0.borrow()[..], b"ABC");
+}
+
+#[test]
+fn test_rom_ranges_ignore_writes() {
+    let source = "
+        .org $0600
+        LDA #$55
+        STA $0300
+        STA $E000
+        LDA #0
+        STA $F00F
+";
+    let (mut sandbox, _) = machine(source, b"");
+    sandbox.cpu.write_memory(0xe000, 0xaa);
+    sandbox.protect(0xe000..=0xffff);
+    assert_eq!(sandbox.exit_code(), None);
+    assert_eq!(sandbox.run(), Ok(0));
+    assert_eq!(sandbox.cpu.read_memory(0x0300), 0x55);
+    assert_eq!(sandbox.cpu.read_memory(0xe000), 0xaa);
+}
@@ -0,0 +1,82 @@
+use rs_nes::snake::{Snake, Status, HEIGHT, WIDTH};
+
+/// Runs frames until the game ends, returning how many it took.
+fn play_out(snake: &mut Snake) -> usize {
+    for frame in 1..=100 {
+        if snake.frame().unwrap() == Status::GameOver {
+            return frame;
+        }
+    }
+    panic!("the game is still running after 100 frames");
+}
+
+fn snake_pixels(snake: &Snake) -> Vec<(usize, usize)> {
+    (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| snake.pixel(x, y) == 1)
+        .collect()
+}
+
+#[test]
+fn test_snake_moves_right_into_the_wall() {
+    let mut snake = Snake::new(7);
+    assert_eq!(snake.frame(), Ok(Status::Running));
+    assert_eq!(snake_pixels(&snake), [(18, 16)]);
+    assert_eq!(snake.frame(), Ok(Status::Running));
+    assert_eq!(snake_pixels(&snake), [(18, 16), (19, 16)]);
+
+    assert_eq!(play_out(&mut snake), 13);
+    assert_eq!(snake_pixels(&snake), [(30, 16), (31, 16)]);
+    // it stays over
+    assert_eq!(snake.frame(), Ok(Status::GameOver));
+}
+
+#[test]
+fn test_steering() {
+    let mut snake = Snake::new(7);
+    snake.frame().unwrap();
+    snake.press(b's');
+    snake.frame().unwrap();
+    assert_eq!(snake_pixels(&snake), [(18, 16), (18, 17)]);
+
+    // turning back on itself is ignored
+    snake.press(b'w');
+    snake.frame().unwrap();
+    assert_eq!(snake_pixels(&snake), [(18, 17), (18, 18)]);
+
+    snake.press(b'a');
+    snake.frame().unwrap();
+    assert_eq!(snake_pixels(&snake), [(17, 18), (18, 18)]);
+    play_out(&mut snake);
+    assert_eq!(snake_pixels(&snake), [(0, 18), (1, 18)]);
+}
+
+#[test]
+fn test_text_frames() {
+    let mut snake = Snake::new(7);
+    snake.frame().unwrap();
+    let text = snake.render_text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), HEIGHT);
+    assert!(lines.iter().all(|line| line.len() == WIDTH));
+    assert_eq!(lines[16], "..................#.............");
+    // one apple, in some color
+    let apple: Vec<char> = text.chars().filter(|c| !".#\n".contains(*c)).collect();
+    assert_eq!(apple.len(), 1);
+
+    // the same seed replays the same game
+    let mut again = Snake::new(7);
+    again.frame().unwrap();
+    assert_eq!(again.render_text(), text);
+}
+
+#[test]
+fn test_ansi_frames() {
+    let snake = Snake::new(7);
+    let ansi = snake.render_ansi();
+    let lines: Vec<&str> = ansi.split("\r\n").filter(|line| !line.is_empty()).collect();
+    assert_eq!(lines.len(), HEIGHT / 2);
+    assert_eq!(lines[0].matches('\u{2580}').count(), WIDTH);
+    // black over black
+    assert!(lines[0].starts_with("\x1b[30;40m\u{2580}"));
+}
@@ -0,0 +1,90 @@
+use rs_nes::cpu::{CpuVariant, CPU};
+use rs_nes::trace::{format_line, TraceFormat, Tracer};
+
+fn cpu_with_program(program: &[u8]) -> CPU {
+    let mut cpu = CPU::with_variant(CpuVariant::Nmos6502);
+    cpu.load(program.to_vec());
+    cpu.reset();
+    cpu
+}
+
+#[test]
+fn test_nestest_format_shows_effective_address() {
+    // LDA $0200,X
+    let mut cpu = cpu_with_program(&[0xbd, 0x00, 0x02]);
+    cpu.register_x = 0x05;
+    cpu.write_memory(0x0205, 0x7f);
+
+    assert_eq!(
+        format_line(&cpu, TraceFormat::Nestest),
+        "8000  BD 00 02  LDA $0200,X @ 0205 = 7F         A:00 X:05 Y:00 P:24 SP:FD CYC:7"
+    );
+}
+
+#[test]
+fn test_nestest_format_indirect_modes() {
+    // LDA ($10),Y
+    let mut cpu = cpu_with_program(&[0xb1, 0x10]);
+    cpu.register_y = 0x02;
+    cpu.write_memory(0x10, 0x00);
+    cpu.write_memory(0x11, 0x03);
+    cpu.write_memory(0x0302, 0x99);
+    assert!(format_line(&cpu, TraceFormat::Nestest).starts_with("8000  B1 10     LDA ($10),Y = 0300 @ 0302 = 99 "));
+
+    // JMP ($02FF) wraps within the page on the NMOS 6502
+    let mut cpu = cpu_with_program(&[0x6c, 0xff, 0x02]);
+    cpu.write_memory(0x02ff, 0x34);
+    cpu.write_memory(0x0200, 0x12);
+    assert!(format_line(&cpu, TraceFormat::Nestest).starts_with("8000  6C FF 02  JMP ($02FF) = 1234 "));
+
+    // unofficial opcodes are starred
+    let cpu = cpu_with_program(&[0x04, 0x10]);
+    assert!(format_line(&cpu, TraceFormat::Nestest).starts_with("8000  04 10    *NOP $10 = 00 "));
+}
+
+#[test]
+fn test_mesen_format() {
+    // STA $0200,X
+    let mut cpu = cpu_with_program(&[0x9d, 0x00, 0x02]);
+    cpu.register_a = 0x42;
+    cpu.register_x = 0x05;
+
+    assert_eq!(
+        format_line(&cpu, TraceFormat::Mesen),
+        "8000  STA $0200,X [$0205] = $00 A:42 X:05 Y:00 S:FD P:nvUbdIzc CYC:7"
+    );
+}
+
+#[test]
+fn test_tracer_filters_by_range() {
+    // LDX #$03; DEX; BNE $8002; BRK
+    let mut cpu = cpu_with_program(&[0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00]);
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::Nestest).with_range(0x8002..=0x8002);
+
+    for _ in 0..7 {
+        tracer.trace(&cpu).unwrap();
+        cpu.step().unwrap();
+    }
+
+    let output = String::from_utf8(tracer.into_inner()).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines.iter().all(|line| line.starts_with("8002  CA        DEX")));
+}
+
+#[test]
+fn test_tracer_starts_at_trigger() {
+    // LDX #$03; DEX; BNE $8002; BRK
+    let mut cpu = cpu_with_program(&[0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00]);
+    let mut tracer = Tracer::new(Vec::new(), TraceFormat::Nestest).with_trigger(|cpu| cpu.register_x == 0x01);
+
+    for _ in 0..8 {
+        tracer.trace(&cpu).unwrap();
+        cpu.step().unwrap();
+    }
+
+    let output = String::from_utf8(tracer.into_inner()).unwrap();
+    let pcs: Vec<&str> = output.lines().map(|line| &line[..4]).collect();
+    // the trigger fires on the last BNE back, and tracing continues past BRK
+    assert_eq!(pcs, ["8003", "8002", "8003", "8005"]);
+}